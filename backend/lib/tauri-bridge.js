@@ -33,39 +33,185 @@ async function listen(event, callback) {
 
 // ===== Terminal Commands =====
 
+/**
+ * Thrown when a command is terminated by `timeout_ms` or `idle_timeout_ms`.
+ * kind is 'total' or 'idle'.
+ */
+class CommandTimeoutError extends Error {
+    constructor(exit) {
+        super(exit.error || 'Command timed out');
+        this.name = 'CommandTimeoutError';
+        this.runId = exit.run_id;
+        this.kind = exit.timed_out;
+        this.exit = exit;
+    }
+}
+
+/**
+ * Thrown when a command is terminated for breaking one of its `limits`.
+ * kind is 'memory'; memory is sampled every couple of seconds.
+ */
+class CommandLimitError extends Error {
+    constructor(exit) {
+        super(exit.error || 'Command exceeded a resource limit');
+        this.name = 'CommandLimitError';
+        this.runId = exit.run_id;
+        this.kind = exit.limit_exceeded;
+        this.exit = exit;
+    }
+}
+
 /**
  * Run a command in the terminal.
+ * Output arrives on this call's own channel; resolves with the exit code.
+ * options: { env, clear_env, venv, conda_env, timeout_ms, idle_timeout_ms, output, batch, app, limits, restart }
+ * limits: { max_rss_mb, nice, max_open_files, max_runtime_ms }; max_runtime_ms is
+ * a per-limit runtime cap, and the shorter of it and timeout_ms applies.
+ * restart: { policy: 'never' | 'on-failure' | 'always', max_retries, backoff_ms, max_backoff_ms };
+ * the call resolves once the command ends for good.
+ * In the default 'lines' output mode the callbacks get (line, payload); when
+ * payload.replace is set the line overwrites the previous one (progress bars).
+ * With options.batch ({ interval_ms, max_bytes, max_interval_bytes, overflow })
+ * output arrives in frames, which are unpacked here; payload.frame carries
+ * the frame's coalesced/dropped/spilled counts. Output beyond
+ * max_interval_bytes before a frame goes out is dropped or spilled.
+ * Rejects with a CommandTimeoutError if a timeout ended the command, or a
+ * CommandLimitError if it broke one of its limits.
  */
-async function runCommand(cmd, args = [], cwd = null, onStdout = null, onStderr = null) {
+async function runCommand(cmd, args = [], cwd = null, onStdout = null, onStderr = null, options = null) {
+    if (!isTauri()) {
+        // Warns and resolves null; no exit event would ever arrive.
+        return invoke('run_command', { cmd, args, cwd, options });
+    }
     const windowId = `win_${Math.random().toString(36).slice(2, 9)}`;
+    const unlisteners = [];
 
     const subscribe = async (eventName, handler) => {
         try {
-            return await listen(eventName, (e) => handler(e.payload ?? e));
+            unlisteners.push(await listen(eventName, (e) => handler(e.payload ?? e)));
         } catch (_) {
-            // noop: listener may not exist; fallback handled below
-            return () => {};
+            // noop: listener may not exist
+        }
+    };
+    const lineOf = (payload) => (payload && payload.line !== undefined ? payload.line : payload);
+    const forward = (callback) => (payload) => {
+        if (payload && Array.isArray(payload.lines)) {
+            payload.lines.forEach(({ text, replace }) => {
+                callback(text, { run_id: payload.run_id, line: text, replace, frame: payload });
+            });
+        } else {
+            callback(lineOf(payload), payload);
         }
     };
 
-    // Prefer namespaced events; fallback to global events used by current Tauri backend
     if (onStdout) {
-        await subscribe(`terminal:stdout:${windowId}`, onStdout);
-        await subscribe('terminal:stdout', onStdout);
+        await subscribe(`terminal:stdout:${windowId}`, forward(onStdout));
     }
     if (onStderr) {
-        await subscribe(`terminal:stderr:${windowId}`, onStderr);
-        await subscribe('terminal:stderr', onStderr);
+        await subscribe(`terminal:stderr:${windowId}`, forward(onStderr));
+    }
+    let resolveExit;
+    const exited = new Promise((resolve) => { resolveExit = resolve; });
+    await subscribe(`terminal:exit:${windowId}`, (payload) => resolveExit(payload));
+
+    let exit;
+    try {
+        await invoke('run_command', { cmd, args, cwd, windowId, options });
+        exit = await exited;
+    } finally {
+        unlisteners.forEach((unlisten) => unlisten());
     }
+    if (exit && exit.timed_out) {
+        throw new CommandTimeoutError(exit);
+    }
+    if (exit && exit.limit_exceeded) {
+        throw new CommandLimitError(exit);
+    }
+    return exit && exit.code !== null && exit.code !== undefined ? exit.code : -1;
+}
+
+/**
+ * Start a command without waiting for it. Resolves with { run_id, pid }.
+ */
+async function spawnCommand(cmd, args = [], cwd = null, windowId = null, options = null) {
+    return invoke('spawn_command', { cmd, args, cwd, windowId, options });
+}
+
+async function waitCommand(runId) {
+    return invoke('wait_command', { runId });
+}
 
-    return invoke('run_command', { cmd, args, cwd, windowId });
+async function getCommandStatus(runId) {
+    return invoke('get_command_status', { runId });
 }
 
 /**
- * Kill a process.
+ * Replay a run's recorded output (after a reload, for example).
+ * Resolves with { run_id, entries: [{ seq, stream, text, replace, at }], next_offset, total }.
  */
-async function killProcess(pid) {
-    return invoke('kill_process', { pid });
+async function getRunOutput(runId, offset = 0, limit = null) {
+    return invoke('get_run_output', { runId, offset, limit });
+}
+
+/**
+ * Subscribe to commands being restarted by their restart policy.
+ * callback gets { run_id, restarts, max_retries, delay_ms, exit }.
+ * Resolves with an unlisten function.
+ */
+async function onCommandRestart(callback) {
+    return listen('terminal:restart', (e) => callback(e.payload ?? e));
+}
+
+/**
+ * Signal a running command. Killed commands are not restarted.
+ */
+async function killCommand(runId, signal = null, tree = true) {
+    return invoke('kill_command', { runId, signal, tree });
+}
+
+/**
+ * Kill a process (and by default its whole process tree).
+ * signal: 'TERM' | 'INT' | 'KILL' | 'HUP'
+ */
+async function killProcess(pid, signal = null, tree = true) {
+    return invoke('kill_process', { pid, signal, tree });
+}
+
+/**
+ * Write input to a running command's stdin (include '\n' for line input)
+ */
+async function writeStdin(runId, data) {
+    return invoke('write_stdin', { runId, data });
+}
+
+/**
+ * Close a running command's stdin so it sees end-of-file
+ */
+async function closeStdin(runId) {
+    return invoke('close_stdin', { runId });
+}
+
+// ===== PTY Sessions =====
+
+/**
+ * Open an interactive PTY session.
+ * options: { cols, rows, cwd, env, shell, args }. Resolves with { session_id, pid }.
+ * Output arrives as raw bytes on `pty:data:<session_id>`, then `pty:exit:<session_id>`.
+ */
+async function ptyOpen(options) {
+    return invoke('pty_open', { options });
+}
+
+async function ptyWrite(sessionId, data) {
+    return invoke('pty_write', { sessionId, data });
+}
+
+async function ptyResize(sessionId, cols, rows) {
+    return invoke('pty_resize', { sessionId, cols, rows });
+}
+
+async function ptyClose(sessionId) {
+    return invoke('pty_close', { sessionId });
 }
 
 // ===== Filesystem Commands =====
@@ -100,10 +246,55 @@ async function removePath(path, recursive = false) {
 
 // ===== Process & System Commands =====
 
+/**
+ * Processes Pinokio manages. source is 'command' for runCommand/spawnCommand
+ * and 'backend' for processes started by pinokiod; app names the app folder.
+ * ports lists the TCP ports the process tree listens on and url guesses where
+ * the app is served.
+ */
 async function getProcesses() {
     return invoke('get_processes', {});
 }
 
+/**
+ * Subscribe to live process updates (CPU/memory summed over each process
+ * tree, refreshed every couple of seconds). Resolves with an unlisten function.
+ */
+async function onProcessesUpdate(callback) {
+    return listen('processes:update', (e) => callback(e.payload ?? e));
+}
+
+/**
+ * Processes that have exited, newest first, with argv, cwd, env overrides,
+ * start/end times, exit code or signal and the Pinokio app they belonged to.
+ */
+async function getProcessHistory(limit = null) {
+    return invoke('get_process_history', { limit });
+}
+
+/**
+ * Subscribe to managed apps opening a TCP port (Linux).
+ * callback gets { pid, run_id, app, port, url }. Resolves with an unlisten function.
+ */
+async function onAppListening(callback) {
+    return listen('app:listening', (e) => callback(e.payload ?? e));
+}
+
+/**
+ * Check ports before launching an app. Resolves with one entry per port:
+ * { port, busy, pid, name, managed, run_id, app }.
+ */
+async function checkPorts(ports) {
+    return invoke('check_ports', { ports });
+}
+
+/**
+ * First port nothing listens on between start and end (inclusive).
+ */
+async function findFreePort(start, end) {
+    return invoke('find_free_port', { start, end });
+}
+
 async function detectConda() {
     return invoke('detect_conda', {});
 }
@@ -112,6 +303,44 @@ async function getSystemResources() {
     return invoke('get_system_resources', {});
 }
 
+// ===== Backend Commands =====
+
+async function getBackendStatus() {
+    return invoke('get_backend_status', {});
+}
+
+async function getBackendPort() {
+    return invoke('get_backend_port', {});
+}
+
+/**
+ * Start the backend again after the supervisor gave up on it
+ * (a `backend:crashed` event with phase 'failed').
+ */
+async function restartBackend() {
+    return invoke('restart_backend', {});
+}
+
+// ===== Application Log =====
+
+/**
+ * Read the application log, oldest first.
+ * filter: { level: 'debug' | 'info' | 'warn' | 'error', target, contains }
+ * since: milliseconds since the epoch. Resolves with [{ ts, level, target, message }].
+ */
+async function getLogs(filter = null, since = null, limit = null) {
+    return invoke('get_logs', { filter, since, limit });
+}
+
+/**
+ * Bundle system info, detected tools, paths, processes, config and recent
+ * logs into a zip in the downloads dir, with secrets redacted.
+ * Resolves to the zip's path.
+ */
+async function exportDiagnostics() {
+    return invoke('export_diagnostics', {});
+}
+
 // ===== Electron Compatibility Shim =====
 
 const electronAPI = {
@@ -129,7 +358,21 @@ if (typeof window !== 'undefined') {
         invoke,
         listen,
         runCommand,
+        CommandTimeoutError,
+        CommandLimitError,
+        spawnCommand,
+        waitCommand,
+        getCommandStatus,
+        getRunOutput,
+        onCommandRestart,
+        killCommand,
+        writeStdin,
+        closeStdin,
         killProcess,
+        ptyOpen,
+        ptyWrite,
+        ptyResize,
+        ptyClose,
         listDirectory,
         readFile,
         writeFile,
@@ -138,8 +381,18 @@ if (typeof window !== 'undefined') {
         createDir,
         removePath,
         getProcesses,
+        onProcessesUpdate,
+        getProcessHistory,
+        onAppListening,
+        checkPorts,
+        findFreePort,
         detectConda,
         getSystemResources,
+        getBackendStatus,
+        getBackendPort,
+        restartBackend,
+        getLogs,
+        exportDiagnostics,
         electronAPI
     };
 
@@ -156,7 +409,21 @@ if (typeof module !== 'undefined' && module && module.exports) {
         invoke,
         listen,
         runCommand,
+        CommandTimeoutError,
+        CommandLimitError,
+        spawnCommand,
+        waitCommand,
+        getCommandStatus,
+        getRunOutput,
+        onCommandRestart,
+        killCommand,
+        writeStdin,
+        closeStdin,
         killProcess,
+        ptyOpen,
+        ptyWrite,
+        ptyResize,
+        ptyClose,
         listDirectory,
         readFile,
         writeFile,
@@ -165,8 +432,18 @@ if (typeof module !== 'undefined' && module && module.exports) {
         createDir,
         removePath,
         getProcesses,
+        onProcessesUpdate,
+        getProcessHistory,
+        onAppListening,
+        checkPorts,
+        findFreePort,
         detectConda,
         getSystemResources,
+        getBackendStatus,
+        getBackendPort,
+        restartBackend,
+        getLogs,
+        exportDiagnostics,
         electronAPI
     };
 }
@@ -0,0 +1,9 @@
+//! Backend lifecycle for Pinokio.
+//! Spawns and supervises the pinokiod Node server.
+
+pub mod ownership;
+pub mod port;
+pub mod readiness;
+pub mod supervisor;
+
+pub use supervisor::{BackendState, BackendStatus};
@@ -0,0 +1,342 @@
+//! Process ownership for the Pinokio launcher.
+//! A lock file in the data dir records the launcher and backend PIDs together
+//! with their start times, so cleanup only touches processes we spawned.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessStatus, System};
+
+use crate::signal::{self, Signal};
+
+/// A PID plus its start time; PIDs alone get recycled.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcessStamp {
+    pub pid: u32,
+    pub start_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LockRecord {
+    pub launcher: Option<ProcessStamp>,
+    pub backend: Option<ProcessStamp>,
+    pub port: Option<u16>,
+    /// Loopback port and token a second launch uses to reach this instance.
+    pub ipc_port: Option<u16>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AcquireError {
+    /// Another live launcher holds the lock.
+    AlreadyRunning(LockRecord),
+    Failed(String),
+}
+
+impl std::fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AcquireError::AlreadyRunning(record) => write!(
+                f,
+                "Pinokio is already running (pid {})",
+                record.launcher.map(|l| l.pid).unwrap_or_default()
+            ),
+            AcquireError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// Pinokio's per-user data directory.
+pub fn data_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(".pinokio")
+}
+
+pub fn lock_path() -> PathBuf {
+    data_dir().join("launcher.lock")
+}
+
+/// Stamp a live process, or `None` if it does not exist.
+pub fn stamp(pid: u32) -> Option<ProcessStamp> {
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    sys.refresh_process(pid);
+    sys.process(pid)
+        .filter(|p| p.status() != ProcessStatus::Zombie)
+        .map(|p| ProcessStamp {
+            pid: pid.as_u32(),
+            start_time: p.start_time(),
+        })
+}
+
+/// Whether the exact process described by `stamp` is still running.
+pub fn is_alive(recorded: &ProcessStamp) -> bool {
+    stamp(recorded.pid).as_ref() == Some(recorded)
+}
+
+/// Terminate a process (and its group) we can prove we started, escalating
+/// to a hard kill after `grace`. Returns false if the process survived.
+pub fn reap(recorded: &ProcessStamp, grace: Duration) -> bool {
+    if !is_alive(recorded) {
+        return true;
+    }
+    let _ = signal::send(recorded.pid, Signal::Term, true);
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if !is_alive(recorded) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let _ = signal::send(recorded.pid, Signal::Kill, true);
+    std::thread::sleep(Duration::from_millis(100));
+    !is_alive(recorded)
+}
+
+pub fn read_record(path: &Path) -> Option<LockRecord> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Write `record` to a file only the owner can read; it holds the IPC token.
+fn write_private(path: &Path, record: &LockRecord, create_new: bool) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if create_new {
+        options.create_new(true);
+    } else {
+        options.create(true).truncate(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    let json = serde_json::to_string_pretty(record).unwrap_or_default();
+    file.write_all(json.as_bytes())
+}
+
+/// Exclusive OS lock on a file next to the lock at `path`, serializing
+/// launchers that check and take over the lock. The lock file itself is
+/// replaced on every write, so it can't carry the OS lock. Released when
+/// the returned file is dropped or the process dies.
+fn lock_guard(path: &Path) -> std::io::Result<File> {
+    let guard = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock.guard"))?;
+    guard.lock()?;
+    Ok(guard)
+}
+
+fn write_record(path: &Path, record: &LockRecord) -> Result<(), String> {
+    let tmp = path.with_extension("lock.tmp");
+    write_private(&tmp, record, false).map_err(|e| format!("Write lock failed: {}", e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Write lock failed: {}", e))
+}
+
+/// Held for the lifetime of the launcher; managed as Tauri state.
+pub struct InstanceLock {
+    path: PathBuf,
+    record: Mutex<LockRecord>,
+    released: AtomicBool,
+}
+
+impl InstanceLock {
+    /// Take the lock at `path`.
+    ///
+    /// Fails if another live launcher holds it. A stale lock left by a dead
+    /// launcher is taken over, and the backend it recorded is reaped if that
+    /// exact process is still running.
+    pub fn acquire(path: PathBuf) -> Result<Self, AcquireError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| AcquireError::Failed(format!("Create data dir failed: {}", e)))?;
+        }
+
+        // Without this two launchers could both judge the lock stale, and the
+        // second would remove the lock the first just created.
+        let _guard =
+            lock_guard(&path).map_err(|e| AcquireError::Failed(format!("Lock guard failed: {}", e)))?;
+
+        let me = stamp(std::process::id());
+        let record = LockRecord {
+            launcher: me,
+            ..Default::default()
+        };
+
+        // Two attempts: the second one runs after clearing a stale lock.
+        for _ in 0..2 {
+            match write_private(&path, &record, true) {
+                Ok(()) => {
+                    return Ok(Self {
+                        path,
+                        record: Mutex::new(record),
+                        released: AtomicBool::new(false),
+                    })
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(AcquireError::Failed(format!("Create lock failed: {}", e))),
+            }
+
+            let previous = read_record(&path).unwrap_or_default();
+            if let Some(launcher) = previous.launcher.filter(|l| Some(*l) != me) {
+                if is_alive(&launcher) {
+                    return Err(AcquireError::AlreadyRunning(previous));
+                }
+            }
+            if let Some(backend) = previous.backend {
+                if is_alive(&backend) {
+                    warn!("ownership", "Reaping orphaned backend (pid {})", backend.pid);
+                    if !reap(&backend, Duration::from_secs(3)) {
+                        return Err(AcquireError::Failed(format!(
+                            "Failed to stop orphaned backend (pid {})",
+                            backend.pid
+                        )));
+                    }
+                }
+            }
+            info!("ownership", "Removing stale lock {}", path.display());
+            fs::remove_file(&path)
+                .map_err(|e| AcquireError::Failed(format!("Remove stale lock failed: {}", e)))?;
+        }
+
+        Err(AcquireError::Failed(format!("Could not acquire lock {}", path.display())))
+    }
+
+    /// Publish the socket a second launch should forward its arguments to.
+    pub fn set_ipc(&self, port: u16, token: String) {
+        let mut record = self.record.lock().unwrap();
+        record.ipc_port = Some(port);
+        record.token = Some(token);
+        self.save(&record);
+    }
+
+    /// Remove the lock file once everything we own has been stopped.
+    /// Later updates are ignored so the lock is not recreated.
+    pub fn release(&self) {
+        let _record = self.record.lock().unwrap();
+        self.released.store(true, Ordering::SeqCst);
+        let _ = fs::remove_file(&self.path);
+    }
+
+    fn save(&self, record: &LockRecord) {
+        if self.released.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Err(e) = write_record(&self.path, record) {
+            warn!("ownership", "{}", e);
+        }
+    }
+
+    /// Record (or clear) the backend process this launcher owns.
+    pub fn set_backend(&self, pid: Option<u32>, port: u16) {
+        let mut record = self.record.lock().unwrap();
+        record.backend = pid.and_then(stamp);
+        record.port = Some(port);
+        self.save(&record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+
+    #[test]
+    fn test_stamp_current_process() {
+        let me = stamp(std::process::id()).expect("Current process should be visible");
+        assert!(is_alive(&me));
+        let recycled = ProcessStamp {
+            start_time: me.start_time + 1,
+            ..me
+        };
+        assert!(!is_alive(&recycled));
+    }
+
+    #[test]
+    fn test_acquire_takes_over_stale_lock() {
+        let path = temp_dir("lock-stale").join("launcher.lock");
+        let stale = LockRecord {
+            launcher: Some(ProcessStamp { pid: std::process::id(), start_time: 0 }),
+            ..Default::default()
+        };
+        write_record(&path, &stale).unwrap();
+
+        let lock = InstanceLock::acquire(path.clone()).expect("Stale lock should be taken over");
+        let record = read_record(&path).unwrap();
+        assert_eq!(record.launcher, stamp(std::process::id()));
+        lock.release();
+        lock.set_backend(None, 42000);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_acquire_waits_for_guard() {
+        let path = temp_dir("lock-guard").join("launcher.lock");
+        let guard = lock_guard(&path).unwrap();
+
+        let contender = {
+            let path = path.clone();
+            std::thread::spawn(move || InstanceLock::acquire(path).is_ok())
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!contender.is_finished());
+        assert!(!path.exists());
+
+        drop(guard);
+        assert!(contender.join().unwrap());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_acquire_refuses_live_launcher() {
+        let path = temp_dir("lock-live").join("launcher.lock");
+        let mut other = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let record = LockRecord {
+            launcher: stamp(other.id()),
+            ..Default::default()
+        };
+        write_record(&path, &record).unwrap();
+
+        assert!(matches!(
+            InstanceLock::acquire(path.clone()),
+            Err(AcquireError::AlreadyRunning(_))
+        ));
+        let _ = other.kill();
+        let _ = other.wait();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_acquire_reaps_orphaned_backend() {
+        let path = temp_dir("lock-orphan").join("launcher.lock");
+        use std::os::unix::process::CommandExt;
+        // Like the real backend, the orphan leads its own process group.
+        let mut orphan = std::process::Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let record = LockRecord {
+            backend: stamp(orphan.id()),
+            ..Default::default()
+        };
+        write_record(&path, &record).unwrap();
+
+        InstanceLock::acquire(path.clone()).expect("Lock should be acquired");
+        let status = orphan.wait().unwrap();
+        assert!(!status.success());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,102 @@
+//! Port selection for the Pinokio backend.
+//! Honors a configured port and falls back to the next free one.
+
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Port pinokiod listens on unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 42000;
+
+/// How many ports above the preferred one are tried before asking the OS.
+const SCAN_RANGE: u16 = 100;
+/// How long a loopback connect may take before the port counts as free.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Read the configured port from `--port <n>`/`--port=<n>` or `PINOKIO_PORT`.
+/// The command line wins over the environment.
+pub fn configured_port() -> Option<u16> {
+    parse_configured_port(std::env::args().skip(1), std::env::var("PINOKIO_PORT").ok())
+}
+
+pub fn parse_configured_port<I: IntoIterator<Item = String>>(args: I, env: Option<String>) -> Option<u16> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = if arg == "--port" {
+            args.next()
+        } else {
+            arg.strip_prefix("--port=").map(str::to_string)
+        };
+        if let Some(port) = value.and_then(|v| v.trim().parse::<u16>().ok()).filter(|p| *p > 0) {
+            return Some(port);
+        }
+    }
+    env.and_then(|v| v.trim().parse::<u16>().ok()).filter(|p| *p > 0)
+}
+
+/// Whether nothing is listening on `port`. A loopback bind alone isn't
+/// enough: with `SO_REUSEADDR` macOS and Windows let it succeed next to a
+/// wildcard listener, so the wildcard addresses are bound too and a
+/// connect is tried.
+pub fn is_port_free(port: u16) -> bool {
+    let bindable = |addr: IpAddr| match TcpListener::bind((addr, port)) {
+        Ok(_) => true,
+        // Hosts without IPv6 can't bind `::` at all.
+        Err(e) => addr.is_ipv6() && e.kind() != ErrorKind::AddrInUse,
+    };
+    bindable(Ipv4Addr::LOCALHOST.into())
+        && bindable(Ipv4Addr::UNSPECIFIED.into())
+        && bindable(Ipv6Addr::UNSPECIFIED.into())
+        && TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, port)), CONNECT_TIMEOUT).is_err()
+}
+
+/// Pick `preferred` if it is free, else the next free port above it, else
+/// any port the OS hands out.
+pub fn select_port(preferred: u16) -> Result<u16, String> {
+    let end = preferred.saturating_add(SCAN_RANGE);
+    if let Some(port) = (preferred..=end).find(|p| is_port_free(*p)) {
+        return Ok(port);
+    }
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("No free port available: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_configured_port() {
+        assert_eq!(parse_configured_port(args(&["--port", "42010"]), None), Some(42010));
+        assert_eq!(parse_configured_port(args(&["--port=42011"]), None), Some(42011));
+        assert_eq!(parse_configured_port(args(&[]), Some("42012".to_string())), Some(42012));
+        assert_eq!(
+            parse_configured_port(args(&["--port", "42013"]), Some("42012".to_string())),
+            Some(42013)
+        );
+        assert_eq!(parse_configured_port(args(&["--port", "nope"]), None), None);
+        assert_eq!(parse_configured_port(args(&[]), Some("0".to_string())), None);
+    }
+
+    #[test]
+    fn test_select_port_skips_busy_port() {
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = busy.local_addr().unwrap().port();
+        let selected = select_port(port).expect("Failed to select port");
+        assert_ne!(selected, port);
+        assert!(is_port_free(selected));
+    }
+
+    #[test]
+    fn test_wildcard_listener_makes_port_busy() {
+        let busy = TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = busy.local_addr().unwrap().port();
+        assert!(!is_port_free(port));
+    }
+}
@@ -0,0 +1,155 @@
+//! Readiness probe for the Pinokio backend.
+//! Polls the pinokiod HTTP health endpoint until it answers with a 2xx.
+
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub struct ProbeConfig {
+    pub path: String,
+    /// Delay between two probe attempts.
+    pub interval: Duration,
+    /// Timeout for a single request.
+    pub request_timeout: Duration,
+    /// Give up when the backend is still not ready after this long.
+    pub deadline: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            path: "/health".to_string(),
+            interval: Duration::from_millis(500),
+            request_timeout: Duration::from_secs(2),
+            deadline: Duration::from_secs(90),
+        }
+    }
+}
+
+/// Extract the status code from an HTTP/1.x status line.
+pub fn parse_status_code(response: &str) -> Option<u16> {
+    let line = response.lines().next()?;
+    let mut parts = line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+/// Send one `GET` to the health endpoint on localhost.
+pub async fn probe_once(port: u16, path: &str, timeout: Duration) -> Result<(), String> {
+    let request = async {
+        let mut stream = TcpStream::connect(("127.0.0.1", port))
+            .await
+            .map_err(|e| format!("Connect failed: {}", e))?;
+        let req = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost:{}\r\nConnection: close\r\n\r\n",
+            path, port
+        );
+        stream
+            .write_all(req.as_bytes())
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+
+        // Only the status line matters; stop reading once we have it.
+        let mut buf = Vec::with_capacity(256);
+        let mut chunk = [0u8; 256];
+        while !buf.windows(2).any(|w| w == b"\r\n") && buf.len() < 1024 {
+            let n = stream
+                .read(&mut chunk)
+                .await
+                .map_err(|e| format!("Read failed: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+
+        match parse_status_code(&String::from_utf8_lossy(&buf)) {
+            Some(code) if (200..300).contains(&code) => Ok(()),
+            Some(code) => Err(format!("Health check returned HTTP {}", code)),
+            None => Err("Malformed health check response".to_string()),
+        }
+    };
+
+    tokio::time::timeout(timeout, request)
+        .await
+        .map_err(|_| format!("Health check timed out after {:?}", timeout))?
+}
+
+/// Poll the health endpoint until it succeeds or the deadline passes.
+/// Returns the number of attempts it took.
+pub async fn wait_until_ready(port: u16, config: &ProbeConfig) -> Result<u32, String> {
+    let started = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let err = match probe_once(port, &config.path, config.request_timeout).await {
+            Ok(()) => return Ok(attempts),
+            Err(e) => e,
+        };
+        if started.elapsed() + config.interval >= config.deadline {
+            return Err(format!(
+                "Backend not ready after {} attempts in {:?}: {}",
+                attempts,
+                started.elapsed(),
+                err
+            ));
+        }
+        tokio::time::sleep(config.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn serve_once(response: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            if let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 512];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_parse_status_code() {
+        assert_eq!(parse_status_code("HTTP/1.1 200 OK\r\n"), Some(200));
+        assert_eq!(parse_status_code("HTTP/1.0 503 Service Unavailable"), Some(503));
+        assert_eq!(parse_status_code("SSH-2.0-OpenSSH"), None);
+        assert_eq!(parse_status_code(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_probe_accepts_2xx() {
+        let port = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await;
+        assert!(probe_once(port, "/health", Duration::from_secs(2)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_probe_rejects_error_status() {
+        let port = serve_once("HTTP/1.1 503 Service Unavailable\r\n\r\n").await;
+        let err = probe_once(port, "/health", Duration::from_secs(2)).await.unwrap_err();
+        assert!(err.contains("503"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_ready_gives_up() {
+        // Bind and drop to get a port that is very likely closed.
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let config = ProbeConfig {
+            interval: Duration::from_millis(10),
+            request_timeout: Duration::from_millis(100),
+            deadline: Duration::from_millis(50),
+            ..Default::default()
+        };
+        assert!(wait_until_ready(port, &config).await.is_err());
+    }
+}
@@ -0,0 +1,481 @@
+//! Backend supervisor for Pinokio.
+//! Owns the pinokiod child, restarts it with exponential backoff and gives up
+//! once it keeps crashing.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+
+use super::ownership::InstanceLock;
+use super::port::DEFAULT_PORT;
+use super::readiness::{self, ProbeConfig};
+use crate::logging;
+use crate::signal;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendPhase {
+    Starting,
+    Ready,
+    Crashed,
+    Restarting,
+    Failed,
+    Stopped,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BackendStatus {
+    pub phase: BackendPhase,
+    pub port: u16,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub last_exit_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub restart_delay_ms: Option<u64>,
+}
+
+impl Default for BackendStatus {
+    fn default() -> Self {
+        Self {
+            phase: BackendPhase::Starting,
+            port: DEFAULT_PORT,
+            pid: None,
+            restarts: 0,
+            last_exit_code: None,
+            last_error: None,
+            restart_delay_ms: None,
+        }
+    }
+}
+
+/// Shared handle to the supervised backend, managed as Tauri state.
+#[derive(Clone, Default)]
+pub struct BackendState {
+    pub status: Arc<Mutex<BackendStatus>>,
+    pub stopping: Arc<AtomicBool>,
+    /// pinokiod entry script, known once `supervise` has started.
+    pub script: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl BackendState {
+    pub fn new(port: u16) -> Self {
+        let state = Self::default();
+        state.update(|s| s.port = port);
+        state
+    }
+
+    /// Apply `f` to the status and return a snapshot of the result.
+    pub fn update<F: FnOnce(&mut BackendStatus)>(&self, f: F) -> BackendStatus {
+        let mut status = self.status.lock().unwrap();
+        f(&mut status);
+        status.clone()
+    }
+
+    pub fn snapshot(&self) -> BackendStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Claim a supervisor that gave up so it can be started again: moves the
+    /// phase from `Failed` to `Starting` and returns the script to run.
+    pub fn take_failed(&self) -> Option<PathBuf> {
+        let mut status = self.status.lock().unwrap();
+        if status.phase != BackendPhase::Failed || self.is_stopping() {
+            return None;
+        }
+        let script = self.script.lock().unwrap().clone()?;
+        status.phase = BackendPhase::Starting;
+        Some(script)
+    }
+}
+
+pub struct SupervisorConfig {
+    /// Delay before the first restart; doubled for each consecutive crash.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Give up after this many crashes within `crash_window`.
+    pub max_crashes: usize,
+    pub crash_window: Duration,
+    /// A backend that became ready and stayed up this long resets the backoff.
+    pub stable_after: Duration,
+    /// Give up after this many health probe timeouts with no ready backend in between.
+    pub max_probe_failures: u32,
+    pub probe: ProbeConfig,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_crashes: 5,
+            crash_window: Duration::from_secs(120),
+            stable_after: Duration::from_secs(60),
+            max_probe_failures: 3,
+            probe: ProbeConfig::default(),
+        }
+    }
+}
+
+/// Sliding window of recent crashes used to detect a crash loop.
+pub struct CrashTracker {
+    crashes: VecDeque<Instant>,
+    max_crashes: usize,
+    window: Duration,
+}
+
+impl CrashTracker {
+    pub fn new(max_crashes: usize, window: Duration) -> Self {
+        Self {
+            crashes: VecDeque::new(),
+            max_crashes,
+            window,
+        }
+    }
+
+    /// Record a crash at `now`. Returns true once the crash-loop limit is hit.
+    pub fn record(&mut self, now: Instant) -> bool {
+        while let Some(first) = self.crashes.front() {
+            if now.duration_since(*first) > self.window {
+                self.crashes.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.crashes.push_back(now);
+        self.crashes.len() >= self.max_crashes
+    }
+}
+
+/// Backoff before restart number `attempt` (1-based).
+pub fn backoff_delay(attempt: u32, initial: Duration, max: Duration) -> Duration {
+    let factor = 1u32 << attempt.saturating_sub(1).min(16);
+    initial.saturating_mul(factor).min(max)
+}
+
+fn emit(app: &AppHandle, event: &str, status: &BackendStatus) {
+    let _ = app.emit_all(event, status.clone());
+}
+
+/// Origin the bundled UI pages are served from.
+fn app_origin() -> &'static str {
+    if cfg!(windows) {
+        "https://tauri.localhost"
+    } else {
+        "tauri://localhost"
+    }
+}
+
+fn navigate(app: &AppHandle, url: &str) {
+    if let Some(window) = app.get_window("main") {
+        let url = serde_json::to_string(url).unwrap_or_default();
+        let _ = window.eval(&format!("window.location.replace({})", url));
+    }
+}
+
+/// Send the main window to the splash error state, pointing at the log dir.
+fn show_failure(app: &AppHandle, message: &str, detail: &str) {
+    if let Some(window) = app.get_window("main") {
+        let log = logging::log_dir().to_string_lossy().to_string();
+        let script = format!(
+            "window.location.replace({} + '/splash.html?state=error&message=' + encodeURIComponent({}) + '&detail=' + encodeURIComponent({}) + '&log=' + encodeURIComponent({}))",
+            serde_json::to_string(app_origin()).unwrap_or_default(),
+            serde_json::to_string(message).unwrap_or_default(),
+            serde_json::to_string(detail).unwrap_or_default(),
+            serde_json::to_string(&log).unwrap_or_default(),
+        );
+        let _ = window.eval(&script);
+    }
+}
+
+/// Keep the launcher lock in sync so a later launch can reap an orphan.
+fn record_backend(app: &AppHandle, pid: Option<u32>, port: u16) {
+    if let Some(lock) = app.try_state::<InstanceLock>() {
+        lock.set_backend(pid, port);
+    }
+}
+
+fn backend_command(script_path: &Path, port: u16) -> Command {
+    let mut cmd = Command::new("node");
+    cmd.arg(script_path);
+    cmd.env("PINOKIO_PORT", port.to_string());
+    cmd.env("PORT", port.to_string());
+
+    // Performance Optimization (v5.3.11)
+    // 1. Increase threadpool for blocking I/O (Filesystem, Crypto, Zlib)
+    cmd.env("UV_THREADPOOL_SIZE", "128");
+    // 2. Allow more RAM for Node.js to reduce GC pauses (4GB)
+    cmd.env("NODE_OPTIONS", "--max-old-space-size=4096");
+
+    // Fix for bundled environment: Set NODE_PATH to node_modules_vendor
+    if let Some(vendor_dir) = script_path.parent().and_then(Path::parent).and_then(Path::parent) {
+        let vendor_path = vendor_dir.to_string_lossy().to_string();
+        debug!("supervisor", "Setting NODE_PATH to: {}", vendor_path);
+        cmd.env("NODE_PATH", vendor_path);
+    }
+
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    // Own process group so shutdown can take the apps pinokiod started with it.
+    signal::isolate(&mut cmd);
+    cmd
+}
+
+/// Forward one backend stream line by line. Invalid UTF-8 is replaced
+/// rather than ending the stream, which would stop draining the pipe.
+fn pump_stream<R>(app: AppHandle, reader: R, is_stderr: bool)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if is_stderr {
+                warn!("pinokiod", "{}", line);
+                eprintln!("[PINOKIO ERR] {}", line);
+            } else {
+                info!("pinokiod", "{}", line);
+                println!("[PINOKIO] {}", line); // Log to terminal
+            }
+            if let Some(window) = app.get_window("main") {
+                let event = if is_stderr { "terminal:stderr" } else { "terminal:stdout" };
+                let _ = window.emit(event, line);
+            }
+        }
+    });
+}
+
+/// Forward the backend's stdout/stderr to the log and the main window.
+fn pump_output(app: &AppHandle, child: &mut Child) {
+    if let Some(stdout) = child.stdout.take() {
+        pump_stream(app.clone(), stdout, false);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        pump_stream(app.clone(), stderr, true);
+    }
+}
+
+/// Run the backend until it is stopped or crash-loops.
+///
+/// The main window is only redirected to the backend once the health probe
+/// passes; a backend that never becomes healthy is killed and restarted.
+/// Emits `backend:starting`, `backend:ready`, `backend:crashed` and
+/// `backend:restarting` with the current [`BackendStatus`] as payload. A
+/// `backend:crashed` event whose phase is `failed` means the supervisor gave up.
+pub async fn supervise(app: AppHandle, script_path: PathBuf, config: SupervisorConfig) {
+    let state = app.state::<BackendState>().inner().clone();
+    let port = state.snapshot().port;
+    *state.script.lock().unwrap() = Some(script_path.clone());
+    let mut tracker = CrashTracker::new(config.max_crashes, config.crash_window);
+    let mut attempt = 0u32;
+    let mut probe_failures = 0u32;
+
+    while !state.is_stopping() {
+        let status = state.update(|s| {
+            s.phase = BackendPhase::Starting;
+            s.pid = None;
+            s.last_error = None;
+            s.restart_delay_ms = None;
+        });
+        emit(&app, "backend:starting", &status);
+        info!("supervisor", "Attempting to spawn backend...");
+
+        let started = Instant::now();
+        let mut ready = false;
+        let mut probe_timed_out = false;
+        let exit_code = match backend_command(&script_path, port).spawn() {
+            Ok(mut child) => {
+                info!("supervisor", "Node process spawned successfully.");
+                state.update(|s| s.pid = child.id());
+                record_backend(&app, child.id(), port);
+                pump_output(&app, &mut child);
+
+                let probe = readiness::wait_until_ready(port, &config.probe);
+                tokio::pin!(probe);
+                let exit = tokio::select! {
+                    exit = child.wait() => exit,
+                    probed = &mut probe => {
+                        match probed {
+                            Ok(attempts) => {
+                                ready = true;
+                                let status = state.update(|s| s.phase = BackendPhase::Ready);
+                                emit(&app, "backend:ready", &status);
+                                info!(
+                                    "readiness",
+                                    "Backend ready after {} probe(s), redirecting...",
+                                    attempts
+                                );
+                                navigate(&app, &format!("http://localhost:{}", port));
+                            }
+                            Err(e) => {
+                                // Alive but never healthy: treat it as a crash.
+                                error!("readiness", "{}", e);
+                                probe_timed_out = true;
+                                state.update(|s| s.last_error = Some(e));
+                                let _ = child.start_kill();
+                            }
+                        }
+                        child.wait().await
+                    }
+                };
+                match exit {
+                    Ok(exit) => exit.code(),
+                    Err(e) => {
+                        error!("supervisor", "Wait on backend failed: {}", e);
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                let msg = format!("Failed to spawn node process: {}", e);
+                error!("supervisor", "{}", msg);
+                eprintln!("{}", msg);
+                if let Some(window) = app.get_window("main") {
+                    let _ = window.emit("terminal:stderr", &format!("Failed to spawn backend: {}", e));
+                }
+                state.update(|s| s.last_error = Some(msg));
+                None
+            }
+        };
+
+        record_backend(&app, None, port);
+        if state.is_stopping() {
+            break;
+        }
+
+        if ready {
+            probe_failures = 0;
+            if started.elapsed() >= config.stable_after {
+                attempt = 0;
+            }
+        } else if probe_timed_out {
+            probe_failures += 1;
+        }
+        let crash_loop = tracker.record(Instant::now());
+        let never_ready = probe_failures >= config.max_probe_failures;
+        let gave_up = crash_loop || never_ready;
+        warn!(
+            "supervisor",
+            "Backend exited (code: {:?}, uptime: {:?})",
+            exit_code,
+            started.elapsed()
+        );
+
+        let status = state.update(|s| {
+            s.phase = if gave_up { BackendPhase::Failed } else { BackendPhase::Crashed };
+            s.pid = None;
+            s.last_exit_code = exit_code;
+        });
+        emit(&app, "backend:crashed", &status);
+        if gave_up {
+            let message = if never_ready {
+                format!("Backend failed its health check {} times, giving up.", probe_failures)
+            } else {
+                format!(
+                    "Backend crashed {} times within {:?}, giving up.",
+                    config.max_crashes, config.crash_window
+                )
+            };
+            error!("supervisor", "{}", message);
+            let detail = status
+                .last_error
+                .unwrap_or_else(|| format!("Last exit code: {:?}", status.last_exit_code));
+            show_failure(&app, &message, &detail);
+            return;
+        }
+
+        attempt += 1;
+        let delay = backoff_delay(attempt, config.initial_backoff, config.max_backoff);
+        let status = state.update(|s| {
+            s.phase = BackendPhase::Restarting;
+            s.restarts += 1;
+            s.restart_delay_ms = Some(delay.as_millis() as u64);
+        });
+        emit(&app, "backend:restarting", &status);
+        info!("supervisor", "Restarting backend in {:?} (attempt {})", delay, attempt);
+        tokio::time::sleep(delay).await;
+    }
+
+    let status = state.update(|s| {
+        s.phase = BackendPhase::Stopped;
+        s.pid = None;
+    });
+    emit(&app, "backend:stopped", &status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let initial = Duration::from_millis(500);
+        let max = Duration::from_secs(30);
+        assert_eq!(backoff_delay(1, initial, max), Duration::from_millis(500));
+        assert_eq!(backoff_delay(2, initial, max), Duration::from_secs(1));
+        assert_eq!(backoff_delay(4, initial, max), Duration::from_secs(4));
+        assert_eq!(backoff_delay(10, initial, max), max);
+        assert_eq!(backoff_delay(u32::MAX, initial, max), max);
+    }
+
+    #[test]
+    fn test_crash_tracker_detects_loop() {
+        let mut tracker = CrashTracker::new(3, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(!tracker.record(start));
+        assert!(!tracker.record(start + Duration::from_secs(1)));
+        assert!(tracker.record(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_crash_tracker_forgets_old_crashes() {
+        let mut tracker = CrashTracker::new(3, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(!tracker.record(start));
+        assert!(!tracker.record(start + Duration::from_secs(1)));
+        assert!(!tracker.record(start + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_take_failed_claims_once() {
+        let state = BackendState::new(DEFAULT_PORT);
+        assert_eq!(state.take_failed(), None);
+        *state.script.lock().unwrap() = Some(PathBuf::from("index.js"));
+        state.update(|s| s.phase = BackendPhase::Failed);
+        assert_eq!(state.take_failed(), Some(PathBuf::from("index.js")));
+        assert_eq!(state.snapshot().phase, BackendPhase::Starting);
+        assert_eq!(state.take_failed(), None);
+    }
+
+    #[test]
+    fn test_backend_status_serialization() {
+        let status = BackendStatus {
+            phase: BackendPhase::Restarting,
+            restarts: 2,
+            restart_delay_ms: Some(1000),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&status).expect("Failed to serialize BackendStatus");
+        assert!(json.contains("\"restarting\""));
+        assert!(json.contains("\"restarts\":2"));
+    }
+}
@@ -0,0 +1,30 @@
+//! Backend status commands for Pinokio.
+//! Exposes the supervised pinokiod state to the frontend.
+
+use tauri::{AppHandle, State};
+
+use crate::backend::supervisor::{self, SupervisorConfig};
+use crate::backend::{BackendState, BackendStatus};
+
+/// Get the current lifecycle status of the pinokiod backend.
+#[tauri::command]
+pub fn get_backend_status(state: State<BackendState>) -> BackendStatus {
+    state.snapshot()
+}
+
+/// Get the port pinokiod was started on.
+#[tauri::command]
+pub fn get_backend_port(state: State<BackendState>) -> u16 {
+    state.snapshot().port
+}
+
+/// Start the backend again after the supervisor gave up on it.
+#[tauri::command]
+pub fn restart_backend(app: AppHandle, state: State<BackendState>) -> Result<(), String> {
+    let script = state
+        .take_failed()
+        .ok_or_else(|| "Backend has not failed".to_string())?;
+    info!("supervisor", "Restarting backend on request");
+    tauri::async_runtime::spawn(supervisor::supervise(app, script, SupervisorConfig::default()));
+    Ok(())
+}
@@ -0,0 +1,394 @@
+//! Output batching for commands run by Pinokio.
+//! Coalesces a stream's output into frames so chatty commands don't flood
+//! the webview with one event per line.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use super::output::Line;
+use crate::logging;
+
+/// What to do with output beyond `max_interval_bytes`.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Discard the oldest buffered output.
+    #[default]
+    Drop,
+    /// Move the oldest buffered output to a spill file on disk.
+    Spill,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BatchOptions {
+    /// Send a frame at most this long after its first output arrived.
+    pub interval_ms: u64,
+    /// Send a frame early once it holds this many bytes.
+    pub max_bytes: usize,
+    /// Cap on output piling up before the next frame goes out. Events are
+    /// fire-and-forget, so this bounds bursts, not how far the webview lags.
+    pub max_interval_bytes: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            interval_ms: 50,
+            max_bytes: 64 * 1024,
+            max_interval_bytes: 4 * 1024 * 1024,
+            overflow: Overflow::Drop,
+        }
+    }
+}
+
+/// One unit of decoded output.
+pub enum Chunk {
+    Line(Line),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Chunk {
+    fn len(&self) -> usize {
+        match self {
+            Chunk::Line(line) => line.text.len() + 1,
+            Chunk::Text(text) => text.len(),
+            Chunk::Bytes(data) => data.len(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Chunk::Line(line) => format!("{}\n", line.text).into_bytes(),
+            Chunk::Text(text) => text.clone().into_bytes(),
+            Chunk::Bytes(data) => data.clone(),
+        }
+    }
+}
+
+/// A frame's output, shaped like the stream's output mode.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum FrameOutput {
+    Lines { lines: Vec<Line> },
+    Text { text: String },
+    Bytes { data: Vec<u8> },
+}
+
+/// Payload of a batched `terminal:stdout:<channel>`/`terminal:stderr:<channel>`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OutputFrame {
+    pub run_id: String,
+    #[serde(flatten)]
+    pub output: FrameOutput,
+    /// Output events merged into this frame.
+    pub coalesced: usize,
+    /// Output events discarded since the previous frame.
+    pub dropped: usize,
+    /// Output events moved to `spill_path` since the previous frame.
+    pub spilled: usize,
+    pub spill_path: Option<String>,
+}
+
+/// Output waiting for the next frame.
+#[derive(Default)]
+pub struct FrameBuffer {
+    items: VecDeque<Chunk>,
+    bytes: usize,
+    coalesced: usize,
+    dropped: usize,
+    spilled: usize,
+    first_at: Option<Instant>,
+    closed: bool,
+}
+
+impl FrameBuffer {
+    /// Queue `chunk`. Returns the oldest output evicted to stay within
+    /// `max_buffered`.
+    pub fn push(&mut self, chunk: Chunk, max_buffered: usize) -> Vec<Chunk> {
+        self.coalesced += 1;
+        self.first_at.get_or_insert_with(Instant::now);
+        // A `\r` update overwrites the line before it, so only the latest counts.
+        if let Chunk::Line(line) = &chunk {
+            if line.replace {
+                if let Some(Chunk::Line(last)) = self.items.back_mut() {
+                    self.bytes = self.bytes - last.text.len() + line.text.len();
+                    last.text = line.text.clone();
+                    return Vec::new();
+                }
+            }
+        }
+        self.bytes += chunk.len();
+        self.items.push_back(chunk);
+
+        let mut evicted = Vec::new();
+        while self.bytes > max_buffered && self.items.len() > 1 {
+            if let Some(old) = self.items.pop_front() {
+                self.bytes -= old.len();
+                evicted.push(old);
+            }
+        }
+        evicted
+    }
+
+    /// Take everything queued as one frame's output and counters.
+    pub fn take(&mut self) -> Option<(FrameOutput, usize, usize, usize)> {
+        if self.items.is_empty() && self.dropped == 0 && self.spilled == 0 {
+            return None;
+        }
+        let items: Vec<Chunk> = self.items.drain(..).collect();
+        let output = match items.first() {
+            Some(Chunk::Text(_)) => FrameOutput::Text {
+                text: items
+                    .iter()
+                    .filter_map(|c| if let Chunk::Text(t) = c { Some(t.as_str()) } else { None })
+                    .collect(),
+            },
+            Some(Chunk::Bytes(_)) => FrameOutput::Bytes {
+                data: items.iter().flat_map(Chunk::to_bytes).collect(),
+            },
+            _ => FrameOutput::Lines {
+                lines: items
+                    .into_iter()
+                    .filter_map(|c| if let Chunk::Line(l) = c { Some(l) } else { None })
+                    .collect(),
+            },
+        };
+        let counts = (self.coalesced, self.dropped, self.spilled);
+        self.bytes = 0;
+        self.coalesced = 0;
+        self.dropped = 0;
+        self.spilled = 0;
+        self.first_at = None;
+        Some((output, counts.0, counts.1, counts.2))
+    }
+}
+
+/// Append `data` to a spill file only the owner can read.
+fn write_spill(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    logging::open_private(path)?.write_all(data)
+}
+
+/// Evicted output waiting to be written to the spill file.
+#[derive(Default)]
+struct SpillQueue {
+    data: Vec<u8>,
+    chunks: usize,
+}
+
+/// A stream's frame buffer shared between its reader and its flusher.
+pub struct Batcher {
+    run_id: String,
+    options: BatchOptions,
+    spill_path: PathBuf,
+    buffer: Mutex<FrameBuffer>,
+    spill: Mutex<SpillQueue>,
+    wake: Notify,
+}
+
+impl Batcher {
+    pub fn new(run_id: &str, options: BatchOptions, spill_path: PathBuf) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            options,
+            spill_path,
+            buffer: Mutex::new(FrameBuffer::default()),
+            spill: Mutex::new(SpillQueue::default()),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, chunk: Chunk) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let evicted = buffer.push(chunk, self.options.max_interval_bytes);
+        if !evicted.is_empty() {
+            match self.options.overflow {
+                Overflow::Drop => buffer.dropped += evicted.len(),
+                Overflow::Spill => {
+                    // Written by the flusher, off the reader's task.
+                    let mut spill = self.spill.lock().unwrap();
+                    spill.chunks += evicted.len();
+                    spill.data.extend(evicted.iter().flat_map(Chunk::to_bytes));
+                }
+            }
+        }
+        drop(buffer);
+        self.wake.notify_one();
+    }
+
+    /// Whether a full frame is waiting; the reader should let the flusher
+    /// run before reading more.
+    pub fn is_full(&self) -> bool {
+        self.buffer.lock().unwrap().bytes >= self.options.max_bytes
+    }
+
+    /// Write queued spill output and count it in the next frame.
+    async fn flush_spill(&self) {
+        let queued = std::mem::take(&mut *self.spill.lock().unwrap());
+        if queued.chunks == 0 {
+            return;
+        }
+        let path = self.spill_path.clone();
+        let written = tokio::task::spawn_blocking(move || write_spill(&path, &queued.data))
+            .await
+            .is_ok_and(|result| result.is_ok());
+        let mut buffer = self.buffer.lock().unwrap();
+        if written {
+            buffer.spilled += queued.chunks;
+        } else {
+            buffer.dropped += queued.chunks;
+        }
+    }
+
+    /// End of stream: the flusher sends what is left and returns.
+    pub fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+        self.wake.notify_one();
+    }
+
+    fn frame(&self) -> (Option<OutputFrame>, bool) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let closed = buffer.closed;
+        let frame = buffer.take().map(|(output, coalesced, dropped, spilled)| OutputFrame {
+            run_id: self.run_id.clone(),
+            output,
+            coalesced,
+            dropped,
+            spilled,
+            spill_path: (spilled > 0).then(|| self.spill_path.to_string_lossy().to_string()),
+        });
+        (frame, closed)
+    }
+
+    /// When the next frame is due, or `None` while there is nothing to send.
+    fn next_due(&self) -> Option<Instant> {
+        let buffer = self.buffer.lock().unwrap();
+        if buffer.closed || buffer.bytes >= self.options.max_bytes {
+            return Some(Instant::now());
+        }
+        buffer
+            .first_at
+            .map(|first_at| first_at + Duration::from_millis(self.options.interval_ms))
+    }
+
+    /// Send frames through `emit` until the stream is closed and drained.
+    pub async fn flush_frames<F: Fn(OutputFrame)>(&self, emit: F) {
+        loop {
+            match self.next_due() {
+                None => {
+                    self.wake.notified().await;
+                    continue;
+                }
+                Some(due) if due > Instant::now() => {
+                    // Woken early by more output, which may fill the frame.
+                    let _ = tokio::time::timeout_at(due.into(), self.wake.notified()).await;
+                    continue;
+                }
+                Some(_) => {}
+            }
+            self.flush_spill().await;
+            let (frame, closed) = self.frame();
+            if let Some(frame) = frame {
+                emit(frame);
+            }
+            if closed {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, replace: bool) -> Chunk {
+        Chunk::Line(Line {
+            text: text.to_string(),
+            replace,
+        })
+    }
+
+    #[test]
+    fn test_frame_coalesces_replace_lines() {
+        let mut buffer = FrameBuffer::default();
+        buffer.push(line("Downloading", false), 1024);
+        buffer.push(line(" 10%", false), 1024);
+        buffer.push(line(" 50%", true), 1024);
+        buffer.push(line("100%", true), 1024);
+
+        let (output, coalesced, dropped, _) = buffer.take().unwrap();
+        let lines = match output {
+            FrameOutput::Lines { lines } => lines,
+            other => panic!("unexpected frame {:?}", other),
+        };
+        let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["Downloading", "100%"]);
+        assert!(!lines[1].replace);
+        assert_eq!((coalesced, dropped), (4, 0));
+        assert!(buffer.take().is_none());
+    }
+
+    #[test]
+    fn test_overflow_evicts_oldest() {
+        let mut buffer = FrameBuffer::default();
+        assert!(buffer.push(Chunk::Text("aaaa".to_string()), 6).is_empty());
+        let evicted = buffer.push(Chunk::Text("bbbb".to_string()), 6);
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(buffer.take().unwrap().0, FrameOutput::Text { text: "bbbb".to_string() });
+    }
+
+    #[tokio::test]
+    async fn test_flush_frames_batches_and_drains() {
+        let dir = crate::tests::temp_dir("batch");
+        let options = BatchOptions {
+            interval_ms: 20,
+            max_interval_bytes: 8,
+            overflow: Overflow::Spill,
+            ..Default::default()
+        };
+        let batcher = Batcher::new("run-1", options, dir.join("run-1.spill"));
+        let frames = Mutex::new(Vec::new());
+
+        let produce = async {
+            for i in 0..5 {
+                batcher.push(Chunk::Bytes(vec![b'0' + i; 3]));
+            }
+            batcher.close();
+        };
+        tokio::join!(batcher.flush_frames(|frame| frames.lock().unwrap().push(frame)), produce);
+
+        let frames = frames.into_inner().unwrap();
+        let json = serde_json::to_value(&frames[0]).unwrap();
+        assert!(json["data"].is_array() && json["coalesced"].is_number());
+        let data: Vec<u8> = frames
+            .iter()
+            .flat_map(|f| match &f.output {
+                FrameOutput::Bytes { data } => data.clone(),
+                _ => Vec::new(),
+            })
+            .collect();
+        assert_eq!(data, b"333444".to_vec());
+        assert_eq!(frames.iter().map(|f| f.coalesced).sum::<usize>(), 5);
+        assert_eq!(frames.iter().map(|f| f.spilled).sum::<usize>(), 3);
+        assert_eq!(std::fs::read(dir.join("run-1.spill")).unwrap(), b"000111222".to_vec());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join("run-1.spill")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o077, 0);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
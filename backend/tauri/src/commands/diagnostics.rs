@@ -0,0 +1,363 @@
+//! Diagnostics bundle for support requests.
+//! Gathers system, tool, path, process, config and log details into one zip
+//! under the downloads dir, with secrets redacted.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use sysinfo::System;
+use tauri::{AppHandle, Manager, State};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::process_manager::{apps_dir, detect_conda, now_ms, ProcessManagerState, MAX_HISTORY};
+use super::redact::{redact_json, redact_text};
+use super::run_log;
+use crate::backend::{ownership, BackendState};
+use crate::logging::{self, LogFilter};
+
+const BACKEND_SCRIPT: &str = "node_modules_vendor/pinokiod/script/index.js";
+/// Application log records included in the bundle.
+const LOG_RECORDS: usize = 5000;
+/// Entries listed per directory.
+const MAX_LISTING: usize = 500;
+/// Environment variables worth including, by prefix.
+const ENV_PREFIXES: &[&str] = &["PINOKIO", "PATH", "CONDA", "NODE", "NPM", "PYTHON", "VIRTUAL_ENV", "SHELL", "LANG"];
+
+fn redacted<T: Serialize>(value: &T) -> Value {
+    let mut value = serde_json::to_value(value).unwrap_or(Value::Null);
+    redact_json(&mut value);
+    value
+}
+
+#[derive(Serialize, Debug)]
+struct Tool {
+    name: String,
+    path: Option<String>,
+    version: Option<String>,
+    error: Option<String>,
+}
+
+/// `name` on the `PATH`, trying `.exe` and `.cmd` on Windows.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let candidates: Vec<String> = if cfg!(windows) {
+        vec![format!("{}.exe", name), format!("{}.cmd", name)]
+    } else {
+        vec![name.to_string()]
+    };
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .flat_map(|dir| candidates.iter().map(move |candidate| dir.join(candidate)))
+        .find(|path| path.is_file())
+}
+
+fn detect_tool(name: &str, path: Option<PathBuf>) -> Tool {
+    let mut tool = Tool {
+        name: name.to_string(),
+        path: path.as_ref().map(|p| p.to_string_lossy().to_string()),
+        version: None,
+        error: None,
+    };
+    let Some(path) = path else {
+        tool.error = Some("Not found".to_string());
+        return tool;
+    };
+    match version_output(&path, VERSION_TIMEOUT) {
+        Ok(output) => {
+            // Older Pythons print their version to stderr.
+            let text = [output.stdout, output.stderr]
+                .iter()
+                .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+                .collect::<String>();
+            tool.version = text.lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string);
+        }
+        Err(e) => tool.error = Some(e),
+    }
+    tool
+}
+
+/// How long a tool gets to answer `--version`.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run `<path> --version`, killing it if it takes longer than `timeout`.
+fn version_output(path: &Path, timeout: Duration) -> Result<std::process::Output, String> {
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Version check failed: {}", e))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Version check timed out after {} ms", timeout.as_millis()));
+            }
+            Err(e) => return Err(format!("Version check failed: {}", e)),
+        }
+    }
+    child
+        .wait_with_output()
+        .map_err(|e| format!("Version check failed: {}", e))
+}
+
+fn tools() -> Vec<Tool> {
+    let mut tools: Vec<Tool> = ["node", "npm", "git", "python3", "python"]
+        .iter()
+        .map(|name| detect_tool(name, find_in_path(name)))
+        .collect();
+    let conda = find_in_path("conda").or_else(|| detect_conda().ok().map(PathBuf::from));
+    tools.push(detect_tool("conda", conda));
+    tools
+}
+
+fn system_info(version: &str) -> Value {
+    let mut sys = System::new();
+    sys.refresh_cpu();
+    sys.refresh_memory();
+    json!({
+        "app_version": version,
+        "created_at": now_ms(),
+        "os": System::name(),
+        "os_version": System::long_os_version(),
+        "kernel_version": System::kernel_version(),
+        "arch": std::env::consts::ARCH,
+        "cpu": sys.cpus().first().map(|cpu| cpu.brand().to_string()),
+        "cpu_count": sys.cpus().len(),
+        "physical_cores": sys.physical_core_count(),
+        "memory_total": sys.total_memory(),
+        "memory_used": sys.used_memory(),
+        "memory_available": sys.available_memory(),
+        "uptime_secs": System::uptime(),
+    })
+}
+
+fn download_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+fn resolved_paths(app: &AppHandle) -> BTreeMap<&'static str, Option<PathBuf>> {
+    let resolver = app.path_resolver();
+    BTreeMap::from([
+        ("resource_dir", resolver.resource_dir()),
+        ("backend_script", resolver.resolve_resource(BACKEND_SCRIPT)),
+        ("data_dir", Some(ownership::data_dir())),
+        ("lock_file", Some(ownership::lock_path())),
+        ("log_dir", Some(logging::log_dir())),
+        ("run_log_dir", Some(run_log::log_dir())),
+        ("apps_dir", Some(apps_dir())),
+        ("downloads_dir", Some(download_dir())),
+    ])
+}
+
+/// Names in `dir`, directories marked with a trailing `/`.
+fn list_dir(dir: &Path) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map_err(|e| format!("List failed: {}", e))?
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => format!("{}/", name),
+                _ => name,
+            }
+        })
+        .collect();
+    names.sort();
+    names.truncate(MAX_LISTING);
+    Ok(names)
+}
+
+/// Listings of the backend script's dirs and the apps dir.
+fn listings(paths: &BTreeMap<&'static str, Option<PathBuf>>) -> BTreeMap<String, Value> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(Some(script)) = paths.get("backend_script") {
+        // script/, pinokiod/ and node_modules_vendor/
+        dirs.extend(script.ancestors().skip(1).take(3).map(Path::to_path_buf));
+    }
+    dirs.push(apps_dir());
+    dirs.into_iter()
+        .map(|dir| {
+            let listing = match list_dir(&dir) {
+                Ok(names) => json!(names),
+                Err(e) => json!({ "error": e }),
+            };
+            (dir.to_string_lossy().to_string(), listing)
+        })
+        .collect()
+}
+
+fn config(backend: &BackendState) -> Value {
+    let env: BTreeMap<String, String> = std::env::vars()
+        .filter(|(key, _)| ENV_PREFIXES.iter().any(|prefix| key.starts_with(prefix)))
+        .collect();
+    redacted(&json!({
+        "lock": ownership::read_record(&ownership::lock_path()),
+        "backend": backend.snapshot(),
+        "env": env,
+    }))
+}
+
+fn json_bytes(value: &impl Serialize) -> Vec<u8> {
+    serde_json::to_vec_pretty(value).unwrap_or_default()
+}
+
+/// Everything that goes into the bundle, as `(file name, contents)`.
+fn collect(app: &AppHandle, state: &ProcessManagerState, backend: &BackendState) -> Vec<(String, Vec<u8>)> {
+    let version = app.package_info().version.to_string();
+    let paths = resolved_paths(app);
+    let path_info: BTreeMap<_, _> = paths
+        .iter()
+        .map(|(name, path)| {
+            let info = path.as_ref().map(|path| json!({ "path": path, "exists": path.exists() }));
+            (*name, info)
+        })
+        .collect();
+    let processes = redacted(&json!({
+        "running": state.list(),
+        "history": state.history(MAX_HISTORY),
+    }));
+    let log: String = logging::logger()
+        .read(&LogFilter::default(), None, LOG_RECORDS)
+        .into_iter()
+        .map(|mut record| {
+            record.message = redact_text(&record.message);
+            serde_json::to_string(&record).unwrap_or_default() + "\n"
+        })
+        .collect();
+
+    let mut files = vec![
+        ("system.json".to_string(), json_bytes(&system_info(&version))),
+        ("tools.json".to_string(), json_bytes(&tools())),
+        ("paths.json".to_string(), json_bytes(&path_info)),
+        ("listings.json".to_string(), json_bytes(&listings(&paths))),
+        ("config.json".to_string(), json_bytes(&config(backend))),
+        ("processes.json".to_string(), json_bytes(&processes)),
+        ("pinokio.log".to_string(), log.into_bytes()),
+    ];
+    // Pinokio's own settings, which hold API keys users have entered.
+    if let Some(home) = apps_dir().parent() {
+        if let Ok(environment) = fs::read_to_string(home.join("ENVIRONMENT")) {
+            files.push(("ENVIRONMENT".to_string(), redact_text(&environment).into_bytes()));
+        }
+    }
+    files
+}
+
+/// Create a new zip for `stamp` in `dir`, readable only by the owner.
+/// Never reuses an existing file: a taken name gets a `-1`, `-2`, ... suffix.
+pub fn create_zip(dir: &Path, stamp: u64) -> Result<(PathBuf, File), String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    for attempt in 0..100 {
+        let name = match attempt {
+            0 => format!("pinokio-diagnostics-{}.zip", stamp),
+            n => format!("pinokio-diagnostics-{}-{}.zip", stamp, n),
+        };
+        let path = dir.join(name);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Diagnostics export failed: {}", e)),
+        }
+    }
+    Err("Diagnostics export failed: too many exports with the same name".to_string())
+}
+
+/// Write `files` into the zip `file`.
+pub fn write_zip(file: File, files: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let mut zip = ZipWriter::new(file);
+    let entry = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, contents) in files {
+        zip.start_file(name.as_str(), entry)
+            .map_err(|e| format!("Diagnostics export failed: {}", e))?;
+        zip.write_all(contents)
+            .map_err(|e| format!("Diagnostics export failed: {}", e))?;
+    }
+    zip.finish().map_err(|e| format!("Diagnostics export failed: {}", e))?;
+    Ok(())
+}
+
+fn export(app: &AppHandle, state: &ProcessManagerState, backend: &BackendState) -> Result<PathBuf, String> {
+    let dir = download_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Diagnostics export failed: {}", e))?;
+    let (path, file) = create_zip(&dir, now_ms() / 1000)?;
+    write_zip(file, &collect(app, state, backend))?;
+    info!("diag", "Exported diagnostics to {}", path.display());
+    Ok(path)
+}
+
+/// Bundle diagnostics into a zip in the downloads dir and return its path.
+#[tauri::command]
+pub async fn export_diagnostics(
+    app: AppHandle,
+    state: State<'_, ProcessManagerState>,
+    backend: State<'_, BackendState>,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    let backend = backend.inner().clone();
+    let path = tauri::async_runtime::spawn_blocking(move || export(&app, &state, &backend))
+        .await
+        .map_err(|e| format!("Diagnostics export failed: {}", e))??;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_write_zip() {
+        let dir = crate::tests::temp_dir("diagnostics-zip");
+        let files = vec![
+            ("system.json".to_string(), b"{\"os\": \"Linux\"}".to_vec()),
+            ("pinokio.log".to_string(), b"line\n".to_vec()),
+        ];
+        let (path, file) = create_zip(&dir, 42).unwrap();
+        write_zip(file, &files).unwrap();
+        // A second export in the same second must not overwrite the first.
+        let (second, _) = create_zip(&dir, 42).unwrap();
+        assert_eq!(second, dir.join("pinokio-diagnostics-42-1.zip"));
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut contents = String::new();
+        archive.by_name("system.json").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "{\"os\": \"Linux\"}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_version_check_times_out() {
+        let dir = crate::tests::temp_dir("diagnostics-tool");
+        let tool = dir.join("slow");
+        fs::write(&tool, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        fs::set_permissions(&tool, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+        let started = Instant::now();
+        let error = version_output(&tool, Duration::from_millis(100)).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(error.contains("timed out"), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,206 @@
+//! Environment setup for commands run by Pinokio.
+//! Applies caller-supplied variables and activates a venv or conda env the
+//! way the `venv` attribute of Pinokio scripts does.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::process_manager::detect_conda;
+
+/// How to set up a command's environment.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct EnvOptions {
+    /// Variables to set, e.g. `PORT`, `GPU_MODE`, `MODEL_PATH`.
+    pub env: HashMap<String, String>,
+    /// Start from an empty environment instead of inheriting the launcher's.
+    /// `PATH` then only comes from `env`.
+    pub clear_env: bool,
+    /// Python venv to activate, relative to the command's cwd.
+    pub venv: Option<String>,
+    /// Conda env to activate, by name (`base` included) or prefix path.
+    pub conda_env: Option<String>,
+}
+
+/// Directories an environment at `prefix` puts on PATH.
+pub fn bin_dirs(prefix: &Path) -> Vec<PathBuf> {
+    #[cfg(windows)]
+    {
+        vec![
+            prefix.to_path_buf(),
+            prefix.join("Library").join("bin"),
+            prefix.join("Scripts"),
+        ]
+    }
+    #[cfg(not(windows))]
+    {
+        vec![prefix.join("bin")]
+    }
+}
+
+/// Locate a venv given relative to `cwd` (or the launcher's cwd).
+pub fn resolve_venv(venv: &str, cwd: Option<&Path>) -> Result<PathBuf, String> {
+    let path = Path::new(venv);
+    let path = match cwd {
+        Some(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(format!("Venv not found: {}", path.display()))
+    }
+}
+
+/// Root of the conda install on the `PATH`. Looked up once, since finding
+/// it runs `which conda`.
+fn system_conda_root() -> Option<PathBuf> {
+    static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        // <root>/bin/conda or <root>\Scripts\conda.exe
+        detect_conda()
+            .ok()
+            .and_then(|conda| Path::new(&conda).parent()?.parent().map(Path::to_path_buf))
+    })
+    .clone()
+}
+
+/// Conda installs to search, Pinokio's bundled one first.
+fn conda_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        roots.push(home.join("pinokio").join("bin").join("miniconda"));
+    }
+    roots.extend(system_conda_root());
+    roots
+}
+
+/// Find a conda env by prefix path or by name under one of `roots`.
+pub fn resolve_conda_env(name: &str, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let path = Path::new(name);
+    if path.components().count() > 1 || path.is_absolute() {
+        return if path.is_dir() {
+            Ok(path.to_path_buf())
+        } else {
+            Err(format!("Conda env not found: {}", name))
+        };
+    }
+    roots
+        .iter()
+        .map(|root| if name == "base" { root.clone() } else { root.join("envs").join(name) })
+        .find(|prefix| prefix.join("conda-meta").is_dir())
+        .ok_or_else(|| format!("Conda env not found: {}", name))
+}
+
+/// `dirs` followed by the entries of `existing`.
+pub fn prepend_path(dirs: &[PathBuf], existing: Option<OsString>) -> Result<OsString, String> {
+    let mut entries = dirs.to_vec();
+    if let Some(existing) = existing {
+        entries.extend(std::env::split_paths(&existing));
+    }
+    std::env::join_paths(entries).map_err(|e| format!("Build PATH failed: {}", e))
+}
+
+/// Apply `options` to `command`, which will run in `cwd`.
+pub fn apply(command: &mut Command, options: &EnvOptions, cwd: Option<&Path>) -> Result<(), String> {
+    if let Some(key) = options.env.keys().find(|k| k.is_empty() || k.contains('=')) {
+        return Err(format!("Invalid environment variable name: {:?}", key));
+    }
+    if options.clear_env {
+        command.env_clear();
+    }
+    command.envs(&options.env);
+
+    let mut prefixes = Vec::new();
+    if let Some(venv) = &options.venv {
+        let dir = resolve_venv(venv, cwd)?;
+        prefixes.extend(bin_dirs(&dir));
+        command.env("VIRTUAL_ENV", &dir);
+    }
+    if let Some(name) = &options.conda_env {
+        let dir = resolve_conda_env(name, &conda_roots())?;
+        prefixes.extend(bin_dirs(&dir));
+        command.env("CONDA_PREFIX", &dir);
+        command.env("CONDA_DEFAULT_ENV", name);
+    }
+    if prefixes.is_empty() {
+        return Ok(());
+    }
+
+    let existing = match options.env.iter().find(|(k, _)| k.eq_ignore_ascii_case("PATH")) {
+        Some((_, value)) => Some(OsString::from(value)),
+        None if !options.clear_env => std::env::var_os("PATH"),
+        None => None,
+    };
+    command.env("PATH", prepend_path(&prefixes, existing)?);
+    // A PYTHONHOME from the launcher's env would point python elsewhere.
+    command.env_remove("PYTHONHOME");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::tests::temp_dir;
+
+    #[test]
+    fn test_resolve_venv_relative_to_cwd() {
+        let app = temp_dir("env-venv");
+        fs::create_dir_all(app.join("env")).unwrap();
+        assert_eq!(resolve_venv("env", Some(&app)).unwrap(), app.join("env"));
+        assert!(resolve_venv("missing", Some(&app)).is_err());
+        let _ = fs::remove_dir_all(&app);
+    }
+
+    #[test]
+    fn test_resolve_conda_env_by_name() {
+        let root = temp_dir("env-conda");
+        fs::create_dir_all(root.join("conda-meta")).unwrap();
+        fs::create_dir_all(root.join("envs").join("sd").join("conda-meta")).unwrap();
+        let roots = vec![root.clone()];
+        assert_eq!(resolve_conda_env("base", &roots).unwrap(), root);
+        assert_eq!(resolve_conda_env("sd", &roots).unwrap(), root.join("envs").join("sd"));
+        assert!(resolve_conda_env("other", &roots).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_prepend_path() {
+        let existing = std::env::join_paths(["/usr/bin", "/bin"]).unwrap();
+        let path = prepend_path(&[PathBuf::from("/app/env/bin")], Some(existing)).unwrap();
+        let entries: Vec<PathBuf> = std::env::split_paths(&path).collect();
+        assert_eq!(
+            entries,
+            vec![PathBuf::from("/app/env/bin"), PathBuf::from("/usr/bin"), PathBuf::from("/bin")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_apply_sets_env_and_path() {
+        let app = temp_dir("env-apply");
+        fs::create_dir_all(app.join("env").join("bin")).unwrap();
+        let mut options = EnvOptions {
+            venv: Some("env".to_string()),
+            clear_env: true,
+            ..Default::default()
+        };
+        options.env.insert("PORT".to_string(), "7860".to_string());
+        options.env.insert("PATH".to_string(), "/usr/bin:/bin".to_string());
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo $PORT $PATH"]);
+        apply(&mut command, &options, Some(&app)).unwrap();
+        let output = command.output().await.unwrap();
+        let expected = format!("7860 {}:/usr/bin:/bin\n", app.join("env").join("bin").display());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+        let _ = fs::remove_dir_all(&app);
+    }
+}
@@ -0,0 +1,141 @@
+//! Resource limits for commands run by Pinokio.
+//! Sets nice level and open-file limits at spawn and decides when a run
+//! broke its limits. RSS is enforced by the monitor's sampling only, so a
+//! run can overshoot `max_rss_mb` for up to one sample interval.
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tokio::sync::watch;
+
+use super::process_manager::ProcessInfo;
+
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Resident memory of the whole process tree, in MiB.
+    pub max_rss_mb: Option<u64>,
+    /// Niceness to start at. Only raising it works without privileges.
+    pub nice: Option<i32>,
+    pub max_open_files: Option<u64>,
+    /// Runtime cap for the run; the shorter of it and `RunOptions::timeout_ms` applies.
+    pub max_runtime_ms: Option<u64>,
+}
+
+/// Which limit a run broke.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitKind {
+    Memory,
+}
+
+/// A broken limit and what to tell the user about it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub kind: LimitKind,
+    pub message: String,
+}
+
+impl Violation {
+    fn memory(used: u64, limit_mb: u64) -> Self {
+        Self {
+            kind: LimitKind::Memory,
+            message: format!("Memory use of {} MiB exceeded the {} MiB limit", used / MIB, limit_mb),
+        }
+    }
+}
+
+/// The memory limit `info` is over, going by its last sample.
+pub fn over_memory(info: &ProcessInfo) -> Option<Violation> {
+    let limit_mb = info.limits.max_rss_mb?;
+    (info.mem_usage > limit_mb * MIB).then(|| Violation::memory(info.mem_usage, limit_mb))
+}
+
+/// Resolve with the first violation `flagged` by the monitor. Never
+/// resolves if there is none.
+pub async fn exceeded(mut flagged: watch::Receiver<Option<Violation>>) -> Violation {
+    match flagged.wait_for(Option::is_some).await.ok().and_then(|v| v.clone()) {
+        Some(violation) => violation,
+        None => std::future::pending().await,
+    }
+}
+
+/// Start `command` at the limits' nice level and open-file limit.
+pub fn apply(command: &mut Command, limits: &ResourceLimits) {
+    #[cfg(unix)]
+    {
+        let nice = limits.nice;
+        let max_open_files = limits.max_open_files;
+        if nice.is_none() && max_open_files.is_none() {
+            return;
+        }
+        // Runs between fork and exec, so only plain system calls.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if let Some(max) = max_open_files {
+                    let limit = libc::rlimit {
+                        rlim_cur: max as libc::rlim_t,
+                        rlim_max: max as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (command, limits);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_over_memory() {
+        let mut info = ProcessInfo {
+            mem_usage: 600 * MIB,
+            ..Default::default()
+        };
+        assert_eq!(over_memory(&info), None);
+        info.limits.max_rss_mb = Some(512);
+        let violation = over_memory(&info).unwrap();
+        assert_eq!(violation.kind, LimitKind::Memory);
+        assert!(violation.message.contains("600 MiB"));
+        info.limits.max_rss_mb = Some(1024);
+        assert_eq!(over_memory(&info), None);
+    }
+
+    #[tokio::test]
+    async fn test_exceeded_by_flag() {
+        let (tx, rx) = watch::channel(None);
+        let pending = tokio::time::timeout(std::time::Duration::from_millis(10), exceeded(rx.clone())).await;
+        assert!(pending.is_err());
+
+        tx.send_replace(Some(Violation::memory(2 * MIB, 1)));
+        let violation = exceeded(rx).await;
+        assert_eq!(violation.kind, LimitKind::Memory);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_apply_sets_open_file_limit() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "ulimit -n"]);
+        let limits = ResourceLimits {
+            max_open_files: Some(64),
+            ..Default::default()
+        };
+        apply(&mut command, &limits);
+        let output = command.output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "64");
+    }
+}
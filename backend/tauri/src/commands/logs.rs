@@ -0,0 +1,19 @@
+//! Log viewer commands for Pinokio.
+//! Reads back the application log written by `crate::logging`.
+
+use crate::logging::{self, LogFilter, Record, DEFAULT_READ_LIMIT};
+
+/// Get the newest log records matching `filter`, oldest first. `since` is in
+/// milliseconds since the Unix epoch.
+#[tauri::command]
+pub async fn get_logs(
+    filter: Option<LogFilter>,
+    since: Option<u64>,
+    limit: Option<usize>,
+) -> Result<Vec<Record>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        logging::logger().read(&filter.unwrap_or_default(), since, limit.unwrap_or(DEFAULT_READ_LIMIT))
+    })
+    .await
+    .map_err(|e| format!("Read logs failed: {}", e))
+}
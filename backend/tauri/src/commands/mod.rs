@@ -1,9 +1,23 @@
 //! Commands module for Pinokio Tauri backend.
 
+pub mod backend;
+pub mod batch;
+pub mod diagnostics;
+pub mod environment;
 pub mod filesystem;
+pub mod limits;
+pub mod logs;
+pub mod monitor;
+pub mod output;
+pub mod ports;
 pub mod terminal;
 pub mod process_manager;
+pub mod pty;
+pub mod redact;
+pub mod restart;
+pub mod run_log;
 
 pub use filesystem::*;
 pub use terminal::*;
 pub use process_manager::ProcessManagerState;
+pub use pty::PtyState;
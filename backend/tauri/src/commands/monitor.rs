@@ -0,0 +1,418 @@
+//! Resource monitoring for processes managed by Pinokio.
+//! Samples CPU and memory for each tracked process and its children,
+//! adopts the processes the pinokiod backend starts, enforces memory limits,
+//! finds the ports apps listen on and pushes the results to the dashboard.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use sysinfo::{Pid, ProcessRefreshKind, ProcessStatus, System, UpdateKind};
+use tauri::{AppHandle, Manager};
+
+use super::limits;
+use super::ports::{self, Listening};
+use super::process_manager::{self, ProcessInfo, ProcessManagerState, ProcessSource};
+use crate::backend::BackendState;
+use crate::signal::{self, Signal};
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the monitor refreshes every process on the system to find new
+/// children of tracked processes. In between it refreshes only the trees it
+/// already knows.
+pub const DISCOVER_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What one refresh saw of a single process.
+#[derive(Clone, Debug)]
+pub struct ProcSample {
+    pub parent: Option<u32>,
+    pub cpu: f32,
+    pub mem: u64,
+    pub status: &'static str,
+    pub name: String,
+    pub cmd: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// Seconds since the Unix epoch.
+    pub start_time: u64,
+}
+
+pub fn status_name(status: ProcessStatus) -> &'static str {
+    match status {
+        ProcessStatus::Zombie => "Zombie",
+        ProcessStatus::Dead => "Exited",
+        ProcessStatus::Stop | ProcessStatus::Tracing => "Stopped",
+        ProcessStatus::Idle
+        | ProcessStatus::Sleep
+        | ProcessStatus::Parked
+        | ProcessStatus::LockBlocked
+        | ProcessStatus::UninterruptibleDiskSleep => "Sleeping",
+        _ => "Running",
+    }
+}
+
+fn refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::new()
+        .with_cpu()
+        .with_memory()
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+        .with_cwd(UpdateKind::OnlyIfNotSet)
+}
+
+/// Refresh every process on the system.
+pub fn collect(sys: &mut System) -> HashMap<u32, ProcSample> {
+    sys.refresh_processes_specifics(refresh_kind());
+    samples(sys)
+}
+
+/// Refresh only `pids`; every other process is dropped from `sys`.
+pub fn collect_pids(sys: &mut System, pids: &[u32]) -> HashMap<u32, ProcSample> {
+    let pids: Vec<Pid> = pids.iter().map(|pid| Pid::from_u32(*pid)).collect();
+    sys.refresh_pids_specifics(&pids, refresh_kind());
+    samples(sys)
+}
+
+fn samples(sys: &System) -> HashMap<u32, ProcSample> {
+    sys.processes()
+        .iter()
+        .map(|(pid, process)| {
+            let sample = ProcSample {
+                parent: process.parent().map(|p| p.as_u32()),
+                cpu: process.cpu_usage(),
+                mem: process.memory(),
+                status: status_name(process.status()),
+                name: process.name().to_string(),
+                cmd: process.cmd().to_vec(),
+                cwd: process.cwd().map(Path::to_path_buf),
+                start_time: process.start_time(),
+            };
+            (pid.as_u32(), sample)
+        })
+        .collect()
+}
+
+/// `root` and everything below it.
+pub fn process_tree(samples: &HashMap<u32, ProcSample>, root: u32) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (pid, sample) in samples {
+        if let Some(parent) = sample.parent {
+            children.entry(parent).or_default().push(*pid);
+        }
+    }
+    let mut tree = vec![root];
+    let mut next = 0;
+    while next < tree.len() {
+        if let Some(kids) = children.get(&tree[next]) {
+            tree.extend(kids.iter().filter(|kid| **kid != root));
+        }
+        next += 1;
+    }
+    tree
+}
+
+/// `roots` and every process `samples` shows below them.
+pub fn watched_pids(samples: &HashMap<u32, ProcSample>, roots: impl IntoIterator<Item = u32>) -> Vec<u32> {
+    let mut pids: Vec<u32> = roots
+        .into_iter()
+        .flat_map(|root| process_tree(samples, root))
+        .collect();
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+/// Send `signal` to `pid` and everything below it. Uses the process group
+/// when `pid` leads one, and otherwise the descendants a fresh sample finds,
+/// deepest first.
+pub fn signal_tree(pid: u32, signal: Signal) -> Result<(), String> {
+    if signal::leads_group(pid) {
+        return signal::send(pid, signal, true);
+    }
+    let samples = collect(&mut System::new());
+    let tree = process_tree(&samples, pid);
+    for descendant in tree.iter().skip(1).rev() {
+        let _ = signal::send(*descendant, signal, false);
+    }
+    signal::send(pid, signal, false)
+}
+
+/// Fill in usage and status of each tracked process from `samples`.
+/// CPU and memory are summed over the process tree.
+pub fn apply_samples(processes: &mut HashMap<u32, ProcessInfo>, samples: &HashMap<u32, ProcSample>) {
+    for (pid, info) in processes.iter_mut() {
+        let Some(leader) = samples.get(pid) else {
+            info.status = "Exited".to_string();
+            info.cpu_usage = 0.0;
+            info.mem_usage = 0;
+            continue;
+        };
+        let tree = process_tree(samples, *pid);
+        info.status = leader.status.to_string();
+        info.cpu_usage = tree.iter().filter_map(|p| samples.get(p)).map(|s| s.cpu).sum();
+        info.mem_usage = tree.iter().filter_map(|p| samples.get(p)).map(|s| s.mem).sum();
+    }
+}
+
+/// Update the ports each tracked process tree listens on from the
+/// `listening` sockets. Returns the ports that opened since the last call.
+pub fn apply_ports(
+    processes: &mut HashMap<u32, ProcessInfo>,
+    samples: &HashMap<u32, ProcSample>,
+    listening: &HashMap<u64, u16>,
+) -> Vec<Listening> {
+    let mut opened = Vec::new();
+    for (pid, info) in processes.iter_mut() {
+        let tree = if samples.contains_key(pid) {
+            process_tree(samples, *pid)
+        } else {
+            Vec::new()
+        };
+        let found = ports::owned_ports(&tree, listening);
+        for port in found.iter().filter(|port| !info.ports.contains(port)) {
+            opened.push(Listening {
+                pid: *pid,
+                run_id: info.run_id.clone(),
+                app: info.app.clone(),
+                port: *port,
+                url: ports::url_for(*port),
+            });
+        }
+        info.url = ports::guess_url(&found);
+        info.ports = found;
+    }
+    opened
+}
+
+/// The app the process tree under `root` works in, going by the first
+/// process whose cwd is inside an app folder.
+fn tree_app(samples: &HashMap<u32, ProcSample>, root: u32, apps_dir: &Path) -> Option<String> {
+    process_tree(samples, root)
+        .iter()
+        .filter_map(|pid| samples.get(pid)?.cwd.as_deref())
+        .find_map(|cwd| process_manager::app_for_path(apps_dir, cwd))
+}
+
+/// Track each child of the pinokiod backend (with its subtree) as a
+/// `ProcessSource::Backend` process. Returns adopted processes that are gone.
+pub fn adopt_backend_tree(
+    processes: &mut HashMap<u32, ProcessInfo>,
+    samples: &HashMap<u32, ProcSample>,
+    backend_pid: Option<u32>,
+    apps_dir: &Path,
+) -> Vec<ProcessInfo> {
+    // A pid that now has a different start time has been reused.
+    let gone: Vec<u32> = processes
+        .iter()
+        .filter(|(_, info)| info.source == ProcessSource::Backend)
+        .filter(|(pid, info)| samples.get(pid).is_none_or(|s| s.start_time * 1000 != info.started_at))
+        .map(|(pid, _)| *pid)
+        .collect();
+    let ended_at = process_manager::now_ms();
+    let gone = gone
+        .into_iter()
+        .filter_map(|pid| processes.remove(&pid))
+        .map(|mut info| {
+            info.status = "Exited".to_string();
+            info.ended_at = Some(ended_at);
+            info
+        })
+        .collect();
+
+    let Some(backend_pid) = backend_pid else {
+        return gone;
+    };
+    for (pid, sample) in samples.iter().filter(|(_, s)| s.parent == Some(backend_pid)) {
+        if let Some(info) = processes.get_mut(pid) {
+            // Apps often `cd` into their folder in a subprocess started later.
+            if info.source == ProcessSource::Backend && info.app.is_none() {
+                info.app = tree_app(samples, *pid, apps_dir);
+            }
+            continue;
+        }
+        processes.insert(
+            *pid,
+            ProcessInfo {
+                pid: *pid,
+                name: sample.name.clone(),
+                status: sample.status.to_string(),
+                argv: sample.cmd.clone(),
+                cwd: sample.cwd.as_ref().map(|cwd| cwd.to_string_lossy().to_string()),
+                started_at: sample.start_time * 1000,
+                app: tree_app(samples, *pid, apps_dir),
+                source: ProcessSource::Backend,
+                ..Default::default()
+            },
+        );
+    }
+    gone
+}
+
+/// Sample tracked processes every `interval` for the lifetime of the app,
+/// emitting `processes:update` with the current list.
+pub fn spawn(app: AppHandle, state: ProcessManagerState, backend: BackendState, interval: Duration) {
+    std::thread::spawn(move || {
+        let mut sys = System::new();
+        let apps_dir = process_manager::apps_dir();
+        let mut was_empty = true;
+        let mut samples = HashMap::new();
+        let mut discovered: Option<Instant> = None;
+        loop {
+            std::thread::sleep(interval);
+            let backend_pid = backend.snapshot().pid;
+            let tracked = state.list();
+            let is_empty = tracked.is_empty() && backend_pid.is_none();
+            if is_empty && was_empty {
+                continue;
+            }
+            samples = if discovered.is_none_or(|at| at.elapsed() >= DISCOVER_INTERVAL) {
+                discovered = Some(Instant::now());
+                collect(&mut sys)
+            } else {
+                let roots = tracked.iter().map(|info| info.pid).chain(backend_pid);
+                collect_pids(&mut sys, &watched_pids(&samples, roots))
+            };
+            let listening = ports::listening_sockets();
+            let (mut snapshot, gone, opened) = {
+                let mut processes = state.processes.lock().unwrap();
+                let gone = adopt_backend_tree(&mut processes, &samples, backend_pid, &apps_dir);
+                apply_samples(&mut processes, &samples);
+                let opened = apply_ports(&mut processes, &samples, &listening);
+                (processes.values().cloned().collect::<Vec<_>>(), gone, opened)
+            };
+            for event in opened {
+                let _ = app.emit_all("app:listening", event);
+            }
+            for info in gone {
+                state.record_history(info);
+            }
+            for info in &snapshot {
+                if let Some(violation) = limits::over_memory(info) {
+                    state.flag_limit(info.pid, violation);
+                }
+            }
+            snapshot.extend(state.restarting());
+            let _ = app.emit_all("processes:update", snapshot);
+            was_empty = is_empty;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(parent: Option<u32>, cpu: f32, mem: u64) -> ProcSample {
+        ProcSample {
+            parent,
+            cpu,
+            mem,
+            status: "Sleeping",
+            name: "python".to_string(),
+            cmd: Vec::new(),
+            cwd: None,
+            start_time: 100,
+        }
+    }
+
+    #[test]
+    fn test_usage_is_summed_over_tree() {
+        let samples: HashMap<u32, ProcSample> = [
+            (1, sample(None, 1.0, 10)),
+            (10, sample(Some(1), 5.0, 100)),
+            (11, sample(Some(10), 20.0, 1000)),
+            (12, sample(Some(10), 2.5, 50)),
+            (20, sample(Some(1), 90.0, 9999)),
+        ]
+        .into_iter()
+        .collect();
+        let info = |pid| ProcessInfo {
+            pid,
+            name: "python".to_string(),
+            status: "Running".to_string(),
+            ..Default::default()
+        };
+        let mut processes: HashMap<u32, ProcessInfo> = [(10, info(10)), (30, info(30))].into_iter().collect();
+
+        apply_samples(&mut processes, &samples);
+        assert_eq!(processes[&10].cpu_usage, 27.5);
+        assert_eq!(processes[&10].mem_usage, 1150);
+        assert_eq!(processes[&10].status, "Sleeping");
+        assert_eq!(processes[&30].status, "Exited");
+    }
+
+    #[test]
+    fn test_adopts_backend_children_by_app() {
+        let apps = Path::new("/home/me/pinokio/api");
+        let mut samples: HashMap<u32, ProcSample> = [
+            (1, sample(None, 0.0, 0)),
+            (50, sample(Some(1), 0.0, 0)),
+            (60, sample(Some(50), 0.0, 0)),
+            (61, sample(Some(60), 0.0, 0)),
+            (70, sample(Some(50), 0.0, 0)),
+            (80, sample(Some(1), 0.0, 0)),
+        ]
+        .into_iter()
+        .collect();
+        samples.get_mut(&61).unwrap().cwd = Some(apps.join("comfyui.git"));
+        let mut processes = HashMap::new();
+
+        assert!(adopt_backend_tree(&mut processes, &samples, Some(50), apps).is_empty());
+        let mut adopted: Vec<u32> = processes.keys().copied().collect();
+        adopted.sort();
+        assert_eq!(adopted, vec![60, 70]);
+        assert_eq!(processes[&60].app.as_deref(), Some("comfyui.git"));
+        assert_eq!(processes[&60].source, ProcessSource::Backend);
+        assert_eq!(processes[&70].app, None);
+
+        samples.remove(&70);
+        let gone = adopt_backend_tree(&mut processes, &samples, Some(50), apps);
+        assert_eq!(gone.len(), 1);
+        assert_eq!(gone[0].pid, 70);
+        assert!(gone[0].ended_at.is_some());
+        assert!(processes.contains_key(&60));
+    }
+
+    #[test]
+    fn test_collect_sees_current_process() {
+        let mut sys = System::new();
+        let samples = collect(&mut sys);
+        let me = samples.get(&std::process::id()).expect("Current process should be sampled");
+        assert!(me.mem > 0);
+
+        let samples = collect_pids(&mut sys, &[std::process::id()]);
+        assert_eq!(samples.keys().copied().collect::<Vec<_>>(), vec![std::process::id()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_tree_without_group() {
+        // Not isolated, so the shell shares the test's process group.
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 30 >/dev/null & echo $!; wait"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        std::io::BufRead::read_line(&mut std::io::BufReader::new(child.stdout.take().unwrap()), &mut line).unwrap();
+        let grandchild: u32 = line.trim().parse().unwrap();
+
+        signal_tree(child.id(), Signal::Kill).unwrap();
+        child.wait().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(crate::backend::ownership::stamp(grandchild).is_none());
+    }
+
+    #[test]
+    fn test_watched_pids_cover_trees() {
+        let samples: HashMap<u32, ProcSample> = [
+            (1, sample(None, 0.0, 0)),
+            (50, sample(Some(1), 0.0, 0)),
+            (60, sample(Some(50), 0.0, 0)),
+            (61, sample(Some(60), 0.0, 0)),
+            (80, sample(Some(1), 0.0, 0)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(watched_pids(&samples, [50, 60, 90]), vec![50, 60, 61, 90]);
+    }
+}
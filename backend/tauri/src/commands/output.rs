@@ -0,0 +1,226 @@
+//! Output decoding for commands run by Pinokio.
+//! Turns raw stdout/stderr bytes into lines or text without losing data on
+//! invalid UTF-8 or `\r` progress updates.
+
+use serde::{Deserialize, Serialize};
+
+/// Lines longer than this are broken up rather than buffered forever.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// How `run_command` emits a stream's output.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// One event per line, with `\r` updates marked as replacing the line.
+    #[default]
+    Lines,
+    /// Chunks of lossily decoded text, as they arrive.
+    Text,
+    /// Chunks of raw bytes, as they arrive.
+    Bytes,
+}
+
+/// Length of the longest prefix of `bytes` that does not end part-way
+/// through a UTF-8 sequence.
+pub fn complete_utf8_len(bytes: &[u8]) -> usize {
+    let len = bytes.len();
+    for back in 1..=len.min(3) {
+        let byte = bytes[len - back];
+        if byte & 0xC0 == 0x80 {
+            // Continuation byte; keep looking for the lead byte.
+            continue;
+        }
+        let needed = match byte {
+            0xF0..=0xF7 => 4,
+            0xE0..=0xEF => 3,
+            0xC0..=0xDF => 2,
+            _ => 1,
+        };
+        return if needed > back { len - back } else { len };
+    }
+    len
+}
+
+/// Decodes a byte stream into text, carrying split characters over to the
+/// next chunk.
+#[derive(Default)]
+pub struct TextDecoder {
+    carry: Vec<u8>,
+}
+
+impl TextDecoder {
+    pub fn feed(&mut self, bytes: &[u8]) -> String {
+        self.carry.extend_from_slice(bytes);
+        let cut = complete_utf8_len(&self.carry);
+        let text = String::from_utf8_lossy(&self.carry[..cut]).into_owned();
+        self.carry.drain(..cut);
+        text
+    }
+
+    /// Decode whatever is left at end of stream.
+    pub fn finish(&mut self) -> String {
+        let text = String::from_utf8_lossy(&self.carry).into_owned();
+        self.carry.clear();
+        text
+    }
+}
+
+/// One decoded line.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub text: String,
+    /// Overwrite the last line shown instead of adding a new one.
+    pub replace: bool,
+}
+
+/// Splits a byte stream into lines, keeping terminal `\r` semantics.
+///
+/// Text after a bare `\r` overwrites the current line, so progress bars
+/// become a series of `replace` lines. A progress line is emitted as soon
+/// as its chunk arrives; ordinary lines wait for their `\n`.
+#[derive(Default)]
+pub struct LineDecoder {
+    buf: Vec<u8>,
+    /// A line has been shown that the next line overwrites.
+    open: bool,
+    /// The current line started after a `\r`.
+    after_cr: bool,
+    /// `buf` has already been emitted as it is.
+    sent: bool,
+    /// A `\r` just ended a line, so a following `\n` adds nothing.
+    cr_ended_line: bool,
+}
+
+impl LineDecoder {
+    fn emit(&mut self, len: usize, out: &mut Vec<Line>) {
+        if !self.sent {
+            out.push(Line {
+                text: String::from_utf8_lossy(&self.buf[..len]).into_owned(),
+                replace: self.open,
+            });
+            self.sent = true;
+        }
+        self.open = true;
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Line> {
+        let mut out = Vec::new();
+        for &byte in bytes {
+            match byte {
+                b'\n' => {
+                    if !self.cr_ended_line {
+                        self.emit(self.buf.len(), &mut out);
+                    }
+                    self.buf.clear();
+                    self.open = false;
+                    self.after_cr = false;
+                    self.sent = false;
+                    self.cr_ended_line = false;
+                }
+                b'\r' => {
+                    if !self.buf.is_empty() {
+                        self.emit(self.buf.len(), &mut out);
+                        self.cr_ended_line = true;
+                    }
+                    self.buf.clear();
+                    self.after_cr = true;
+                    self.sent = false;
+                }
+                _ => {
+                    self.buf.push(byte);
+                    self.sent = false;
+                    self.cr_ended_line = false;
+                    if self.buf.len() >= MAX_LINE_BYTES {
+                        let cut = complete_utf8_len(&self.buf);
+                        self.emit(cut, &mut out);
+                        self.buf.drain(..cut);
+                        self.open = false;
+                        self.sent = false;
+                    }
+                }
+            }
+        }
+        // Show progress lines now rather than at the next `\r`.
+        if self.after_cr && !self.buf.is_empty() {
+            self.emit(complete_utf8_len(&self.buf), &mut out);
+        }
+        out
+    }
+
+    /// Flush a final line that never got its `\n`.
+    pub fn finish(&mut self) -> Option<Line> {
+        let mut out = Vec::new();
+        if !self.buf.is_empty() {
+            self.emit(self.buf.len(), &mut out);
+        }
+        *self = Self::default();
+        out.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, replace: bool) -> Line {
+        Line {
+            text: text.to_string(),
+            replace,
+        }
+    }
+
+    #[test]
+    fn test_complete_utf8_len() {
+        let text = "añ€😀".as_bytes();
+        assert_eq!(complete_utf8_len(text), text.len());
+        assert_eq!(complete_utf8_len(&text[..2]), 1);
+        assert_eq!(complete_utf8_len(&text[..5]), 3);
+        assert_eq!(complete_utf8_len(&text[..8]), 6);
+        assert_eq!(complete_utf8_len(b"ok\xff"), 3);
+    }
+
+    #[test]
+    fn test_text_decoder_joins_split_characters() {
+        let bytes = "€uro".as_bytes();
+        let mut decoder = TextDecoder::default();
+        assert_eq!(decoder.feed(&bytes[..1]), "");
+        assert_eq!(decoder.feed(&bytes[1..]), "€uro");
+        assert_eq!(decoder.feed(b"\xe2\x82"), "");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+    }
+
+    #[test]
+    fn test_lines_survive_invalid_utf8_and_partial_end() {
+        let mut decoder = LineDecoder::default();
+        assert_eq!(decoder.feed(b"bad \xff byte\nlast"), vec![line("bad \u{fffd} byte", false)]);
+        assert_eq!(decoder.feed(b" line"), vec![]);
+        assert_eq!(decoder.finish(), Some(line("last line", false)));
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_carriage_return_replaces_line() {
+        let mut decoder = LineDecoder::default();
+        let mut lines = decoder.feed(b"Downloading\n\r 10%");
+        lines.extend(decoder.feed(b"\r 50%\r10"));
+        lines.extend(decoder.feed(b"0%\ndone\r\n"));
+        assert_eq!(
+            lines,
+            vec![
+                line("Downloading", false),
+                line(" 10%", false),
+                line(" 50%", true),
+                line("10", true),
+                line("100%", true),
+                line("done", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_crlf_split_across_chunks() {
+        let mut decoder = LineDecoder::default();
+        assert_eq!(decoder.feed(b"one\r"), vec![line("one", false)]);
+        assert_eq!(decoder.feed(b"\n\r\ntwo\r\n"), vec![line("", false), line("two", false)]);
+    }
+}
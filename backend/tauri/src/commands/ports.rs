@@ -0,0 +1,293 @@
+//! Port discovery for processes managed by Pinokio.
+//! Finds the TCP ports a process tree listens on by matching its socket
+//! inodes against `/proc/net/tcp` (Linux only), and checks ports for
+//! conflicts before an app is launched.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sysinfo::System;
+use tauri::State;
+
+use super::monitor::{self, ProcSample};
+use super::process_manager::{ProcessInfo, ProcessManagerState};
+use crate::backend::port;
+
+/// Payload of `app:listening`, sent when a managed process opens a port.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Listening {
+    pub pid: u32,
+    pub run_id: Option<String>,
+    pub app: Option<String>,
+    pub port: u16,
+    pub url: String,
+}
+
+/// Result of `check_ports` for one port.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct PortStatus {
+    pub port: u16,
+    pub busy: bool,
+    /// The process listening on the port, when it can be found.
+    pub pid: Option<u32>,
+    pub name: Option<String>,
+    /// The owner is, or runs under, a process Pinokio manages.
+    pub managed: bool,
+    pub run_id: Option<String>,
+    pub app: Option<String>,
+}
+
+/// TCP state of a listening socket in `/proc/net/tcp`.
+const TCP_LISTEN: &str = "0A";
+
+/// Socket inode to port for each listening socket in a `/proc/net/tcp` table.
+pub fn parse_listening(table: &str) -> HashMap<u64, u16> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&TCP_LISTEN) {
+                return None;
+            }
+            let port = fields.get(1)?.rsplit(':').next()?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let inode = fields.get(9)?.parse().ok()?;
+            Some((inode, port))
+        })
+        .collect()
+}
+
+/// Every listening TCP socket on the system, by inode.
+pub fn listening_sockets() -> HashMap<u64, u16> {
+    let mut sockets = HashMap::new();
+    #[cfg(target_os = "linux")]
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(contents) = std::fs::read_to_string(table) {
+            sockets.extend(parse_listening(&contents));
+        }
+    }
+    sockets
+}
+
+/// Inodes of the sockets `pid` has open.
+fn socket_inodes(pid: u32) -> Vec<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            return Vec::new();
+        };
+        fds.flatten()
+            .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+            .filter_map(|target| {
+                let target = target.to_string_lossy();
+                target.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+            })
+            .collect()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = pid;
+        Vec::new()
+    }
+}
+
+/// Ports any of `pids` listens on, sorted.
+pub fn owned_ports(pids: &[u32], listening: &HashMap<u64, u16>) -> Vec<u16> {
+    if listening.is_empty() {
+        return Vec::new();
+    }
+    let mut ports: Vec<u16> = pids
+        .iter()
+        .flat_map(|pid| socket_inodes(*pid))
+        .filter_map(|inode| listening.get(&inode).copied())
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+pub fn url_for(port: u16) -> String {
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// Where the app is most likely served: its lowest listening port.
+pub fn guess_url(ports: &[u16]) -> Option<String> {
+    ports.iter().min().map(|port| url_for(*port))
+}
+
+/// The process holding one of the socket `inodes`.
+fn socket_owner(inodes: &[u64]) -> Option<u32> {
+    #[cfg(target_os = "linux")]
+    {
+        std::fs::read_dir("/proc")
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .find(|pid| socket_inodes(*pid).iter().any(|inode| inodes.contains(inode)))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = inodes;
+        None
+    }
+}
+
+/// The managed process `pid` is or runs under.
+fn managing<'a>(
+    processes: &'a HashMap<u32, ProcessInfo>,
+    samples: &HashMap<u32, ProcSample>,
+    pid: u32,
+) -> Option<&'a ProcessInfo> {
+    let mut current = pid;
+    // Bounded in case of a parent loop in a stale sample.
+    for _ in 0..64 {
+        if let Some(info) = processes.get(&current) {
+            return Some(info);
+        }
+        current = samples.get(&current)?.parent?;
+    }
+    None
+}
+
+/// Whether `port` is taken and by whom.
+pub fn check_port(
+    port: u16,
+    listening: &HashMap<u64, u16>,
+    samples: &HashMap<u32, ProcSample>,
+    processes: &HashMap<u32, ProcessInfo>,
+) -> PortStatus {
+    let inodes: Vec<u64> = listening
+        .iter()
+        .filter(|(_, p)| **p == port)
+        .map(|(inode, _)| *inode)
+        .collect();
+    let busy = !inodes.is_empty() || !port::is_port_free(port);
+    let mut status = PortStatus {
+        port,
+        busy,
+        ..Default::default()
+    };
+    if inodes.is_empty() {
+        return status;
+    }
+    if let Some(pid) = socket_owner(&inodes) {
+        status.pid = Some(pid);
+        status.name = samples.get(&pid).map(|sample| sample.name.clone());
+        if let Some(info) = managing(processes, samples, pid) {
+            status.managed = true;
+            status.run_id = info.run_id.clone();
+            status.app = info.app.clone();
+        }
+    }
+    status
+}
+
+/// First free port in `start..=end`.
+pub fn free_port_in(start: u16, end: u16) -> Result<u16, String> {
+    // Port 0 means "any port" to bind, so it always looks free.
+    if start == 0 || start > end {
+        return Err(format!("Invalid port range: {}-{}", start, end));
+    }
+    (start..=end)
+        .find(|p| port::is_port_free(*p))
+        .ok_or_else(|| format!("No free port between {} and {}", start, end))
+}
+
+/// Report which of `ports` are busy and which process owns them. Port 0 is
+/// skipped.
+#[tauri::command]
+pub async fn check_ports(state: State<'_, ProcessManagerState>, ports: Vec<u16>) -> Result<Vec<PortStatus>, String> {
+    let state = state.inner().clone();
+    // Samples every process and walks /proc, so keep it off the IPC thread.
+    tauri::async_runtime::spawn_blocking(move || {
+        let listening = listening_sockets();
+        let samples = monitor::collect(&mut System::new());
+        let processes = state.processes.lock().unwrap().clone();
+        ports
+            .into_iter()
+            .filter(|port| *port != 0)
+            .map(|port| check_port(port, &listening, &samples, &processes))
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Port check failed: {}", e))
+}
+
+/// Find a port nothing listens on in `start..=end`.
+#[tauri::command]
+pub async fn find_free_port(start: u16, end: u16) -> Result<u16, String> {
+    tauri::async_runtime::spawn_blocking(move || free_port_in(start, end))
+        .await
+        .map_err(|e| format!("Port search failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1EB4 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41234 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1EB4 0100007F:D2F0 01 00000000:00000000 00:00000000 00000000  1000        0 41299 1 0000000000000000 20 4 30 10 -1
+   2: 00000000:1FFC 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41300 1 0000000000000000 100 0 0 10 0
+";
+
+    #[test]
+    fn test_parse_listening() {
+        let sockets = parse_listening(TABLE);
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(sockets[&41234], 7860);
+        assert_eq!(sockets[&41300], 8188);
+    }
+
+    #[test]
+    fn test_guess_url() {
+        assert_eq!(guess_url(&[8188, 7860]), Some("http://127.0.0.1:7860".to_string()));
+        assert_eq!(guess_url(&[]), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_check_port_names_owner() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let listening = listening_sockets();
+        let samples = monitor::collect(&mut System::new());
+        let mut processes = HashMap::new();
+
+        let status = check_port(port, &listening, &samples, &processes);
+        assert!(status.busy);
+        assert_eq!(status.pid, Some(std::process::id()));
+        assert!(!status.managed);
+
+        let me = ProcessInfo {
+            pid: std::process::id(),
+            run_id: Some("run-1".to_string()),
+            ..Default::default()
+        };
+        processes.insert(me.pid, me);
+        let status = check_port(port, &listening, &samples, &processes);
+        assert!(status.managed);
+        assert_eq!(status.run_id.as_deref(), Some("run-1"));
+    }
+
+    #[test]
+    fn test_free_port_in() {
+        let busy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = busy.local_addr().unwrap().port();
+        assert!(free_port_in(port, port).is_err());
+        assert!(free_port_in(10, 1).is_err());
+        assert!(free_port_in(0, 10).is_err());
+        let free = free_port_in(port, port.saturating_add(50)).unwrap();
+        assert_ne!(free, port);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_finds_own_listener() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ports = owned_ports(&[std::process::id()], &listening_sockets());
+        assert!(ports.contains(&port));
+    }
+}
@@ -1,29 +1,571 @@
 //! Process Manager for Pinokio.
 //! Handles environment management (Conda/Python) and monitoring.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use tauri::State;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{watch, Notify};
 
-#[derive(Serialize, Deserialize, Clone)]
+use super::limits::{LimitKind, ResourceLimits, Violation};
+use super::redact::{self, REDACTED};
+use super::restart::RestartOptions;
+use super::run_log::{self, RunLog, RunOutput};
+
+/// Finished runs kept around for `wait_command`/`get_command_status`.
+const MAX_FINISHED_RUNS: usize = 100;
+/// Exited processes kept for `get_process_history`.
+pub const MAX_HISTORY: usize = 200;
+
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Where Pinokio installs apps, one folder each.
+pub fn apps_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join("pinokio").join("api")
+}
+
+/// The app whose folder under `apps_dir` contains `path`.
+pub fn app_for_path(apps_dir: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(apps_dir)
+        .ok()?
+        .components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+}
+
+/// `env` as it may be shown to the frontend: values of secret-looking
+/// variables are masked, and tokens in the others too.
+pub fn redact_env(env: &HashMap<String, String>) -> HashMap<String, String> {
+    env.iter()
+        .map(|(key, value)| {
+            let value = if redact::is_secret(key) {
+                REDACTED.to_string()
+            } else {
+                redact::redact_text(value)
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// Who started a process.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessSource {
+    /// `run_command`/`spawn_command`.
+    #[default]
+    Command,
+    /// The pinokiod backend, found by walking its process tree.
+    Backend,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ProcessInfo {
     pub pid: u32,
     pub name: String,
     pub status: String,
     pub cpu_usage: f32,
     pub mem_usage: u64,
+    pub run_id: Option<String>,
+    /// Program and arguments as started.
+    pub argv: Vec<String>,
+    pub cwd: Option<String>,
+    /// Variables set on top of the inherited environment, with secrets
+    /// such as `HF_TOKEN` masked; see `redact_env`.
+    pub env: HashMap<String, String>,
+    /// Milliseconds since the Unix epoch.
+    pub started_at: u64,
+    pub ended_at: Option<u64>,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// The Pinokio app (folder under `~/pinokio/api`) the process belongs to.
+    pub app: Option<String>,
+    #[serde(default)]
+    pub source: ProcessSource,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    #[serde(default)]
+    pub restart: RestartOptions,
+    /// Times the run has been restarted under its restart policy.
+    #[serde(default)]
+    pub restarts: u32,
+    /// TCP ports the process tree listens on.
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Where the app is probably served, going by `ports`.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    Exited,
+    Killed,
+    TimedOut,
+    LimitExceeded,
+    /// Ended and waiting to be started again by its restart policy.
+    Restarting,
+}
+
+/// Which limit ended a run that timed out.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutKind {
+    /// `timeout_ms` elapsed.
+    Total,
+    /// No output for `idle_timeout_ms`.
+    Idle,
+}
+
+/// How a run ended. Also the payload of `terminal:exit`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ExitStatus {
+    pub run_id: String,
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub success: bool,
+    /// Set when Pinokio terminated the run for taking too long.
+    pub timed_out: Option<TimeoutKind>,
+    /// Set when the run was terminated for breaking a resource limit.
+    pub limit_exceeded: Option<LimitKind>,
+    /// Why the run failed, when that is more than its exit code.
+    pub error: Option<String>,
+}
+
+impl ExitStatus {
+    pub fn from_std(run_id: &str, status: &std::process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(status);
+        #[cfg(not(unix))]
+        let signal = None;
+        Self {
+            run_id: run_id.to_string(),
+            code: status.code(),
+            signal,
+            success: status.success(),
+            timed_out: None,
+            limit_exceeded: None,
+            error: None,
+        }
+    }
+
+    /// Mark a run as terminated because of `kind`.
+    pub fn timed_out(mut self, kind: TimeoutKind, message: String) -> Self {
+        self.success = false;
+        self.timed_out = Some(kind);
+        self.error = Some(message);
+        self
+    }
+
+    /// Mark a run as terminated for breaking a resource limit.
+    pub fn limit_exceeded(mut self, violation: Violation) -> Self {
+        self.success = false;
+        self.limit_exceeded = Some(violation.kind);
+        self.error = Some(violation.message);
+        self
+    }
+
+    /// Status for a run whose exit could not be observed.
+    pub fn unknown(run_id: &str) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            code: None,
+            signal: None,
+            success: false,
+            timed_out: None,
+            limit_exceeded: None,
+            error: None,
+        }
+    }
+}
+
+/// Write end of a run's stdin, shared so writes don't hold the state lock.
+pub type StdinHandle = Arc<tokio::sync::Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+
+/// A command started through `run_command`/`spawn_command`.
+pub struct RunEntry {
+    pub pid: u32,
+    pub state: RunState,
+    pub exit: watch::Receiver<Option<ExitStatus>>,
+    pub finished_at: Option<Instant>,
+    pub stdin: Option<StdinHandle>,
+    pub log: Option<Arc<RunLog>>,
+    /// Where the monitor reports a broken resource limit.
+    pub limit: Option<watch::Sender<Option<Violation>>>,
+    /// Set by `kill_command`; the run is not restarted after that.
+    pub stop_requested: bool,
+    pub stop: Arc<Notify>,
+    /// The process info of a run waiting to restart. It is kept out of
+    /// `processes` so the reaped PID is never signalled or sampled.
+    pub parked: Option<ProcessInfo>,
 }
 
+#[derive(Serialize, Clone, Debug)]
+pub struct CommandStatus {
+    pub run_id: String,
+    pub pid: u32,
+    pub state: RunState,
+    pub exit: Option<ExitStatus>,
+}
+
+#[derive(Clone, Default)]
 pub struct ProcessManagerState {
     pub processes: Arc<Mutex<HashMap<u32, ProcessInfo>>>,
+    pub runs: Arc<Mutex<HashMap<String, RunEntry>>>,
+    /// Exited processes, oldest first.
+    pub history: Arc<Mutex<VecDeque<ProcessInfo>>>,
+}
+
+impl ProcessManagerState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track a freshly spawned run. The returned sender reports its exit.
+    pub fn register_run(&self, run_id: &str, info: ProcessInfo) -> watch::Sender<Option<ExitStatus>> {
+        let (tx, rx) = watch::channel(None);
+        let pid = info.pid;
+        self.processes.lock().unwrap().insert(pid, info);
+        self.runs.lock().unwrap().insert(
+            run_id.to_string(),
+            RunEntry {
+                pid,
+                state: RunState::Running,
+                exit: rx,
+                finished_at: None,
+                stdin: None,
+                log: None,
+                limit: None,
+                stop_requested: false,
+                stop: Arc::new(Notify::new()),
+                parked: None,
+            },
+        );
+        tx
+    }
+
+    /// Keep the write end of a run's stdin for `write_stdin`.
+    pub fn attach_stdin<W: AsyncWrite + Send + Unpin + 'static>(&self, run_id: &str, stdin: W) {
+        if let Some(entry) = self.runs.lock().unwrap().get_mut(run_id) {
+            entry.stdin = Some(Arc::new(tokio::sync::Mutex::new(Box::new(stdin))));
+        }
+    }
+
+    /// Keep a run's output history for `get_run_output`.
+    pub fn attach_log(&self, run_id: &str, log: Arc<RunLog>) {
+        if let Some(entry) = self.runs.lock().unwrap().get_mut(run_id) {
+            entry.log = Some(log);
+        }
+    }
+
+    pub fn attach_limits(&self, run_id: &str, tx: watch::Sender<Option<Violation>>) {
+        if let Some(entry) = self.runs.lock().unwrap().get_mut(run_id) {
+            entry.limit = Some(tx);
+        }
+    }
+
+    /// Report that the running process `pid` broke a limit. Only the first
+    /// report for a run counts.
+    pub fn flag_limit(&self, pid: u32, violation: Violation) {
+        let runs = self.runs.lock().unwrap();
+        let entry = runs.values().find(|e| e.pid == pid && e.state == RunState::Running);
+        if let Some(tx) = entry.and_then(|e| e.limit.as_ref()) {
+            tx.send_if_modified(|current| {
+                if current.is_some() {
+                    return false;
+                }
+                *current = Some(violation);
+                true
+            });
+        }
+    }
+
+    /// A run's recorded output from `offset`. Runs no longer tracked are
+    /// read back from their log file in `dir`.
+    pub fn run_output(&self, dir: &Path, run_id: &str, offset: u64, limit: usize) -> Result<RunOutput, String> {
+        let log = self.runs.lock().unwrap().get(run_id).and_then(|entry| entry.log.clone());
+        if let Some(log) = log {
+            return log.read(run_id, offset, limit);
+        }
+        let path = run_log::log_path(dir, run_id)?;
+        if !path.exists() {
+            return Err(format!("Unknown run: {}", run_id));
+        }
+        let (entries, total) = run_log::read_file(&path, offset, limit)?;
+        Ok(RunOutput {
+            run_id: run_id.to_string(),
+            next_offset: entries.last().map(|e| e.seq + 1).unwrap_or(offset.max(total)),
+            entries,
+            total,
+        })
+    }
+
+    fn stdin_handle(&self, run_id: &str) -> Result<StdinHandle, String> {
+        match self.runs.lock().unwrap().get(run_id) {
+            Some(entry) if entry.state != RunState::Running => Err(format!("Run {} has already exited", run_id)),
+            Some(entry) => entry
+                .stdin
+                .clone()
+                .ok_or_else(|| format!("Stdin of run {} is closed", run_id)),
+            None => Err(format!("Unknown run: {}", run_id)),
+        }
+    }
+
+    /// Write `data` to a run's stdin.
+    pub async fn write_stdin(&self, run_id: &str, data: &[u8]) -> Result<(), String> {
+        let handle = self.stdin_handle(run_id)?;
+        let mut stdin = handle.lock().await;
+        stdin
+            .write_all(data)
+            .await
+            .map_err(|e| format!("Write to stdin failed: {}", e))?;
+        stdin.flush().await.map_err(|e| format!("Write to stdin failed: {}", e))
+    }
+
+    /// Close a run's stdin so it sees EOF.
+    pub fn close_stdin(&self, run_id: &str) -> Result<(), String> {
+        self.stdin_handle(run_id)?;
+        if let Some(entry) = self.runs.lock().unwrap().get_mut(run_id) {
+            entry.stdin = None;
+        }
+        Ok(())
+    }
+
+    /// Mark a run as finished and drop its live process entry.
+    pub fn finish_run(&self, exit: ExitStatus, tx: &watch::Sender<Option<ExitStatus>>) {
+        let mut runs = self.runs.lock().unwrap();
+        if let Some(entry) = runs.get_mut(&exit.run_id) {
+            // A restarting run's last process is already in the history,
+            // and its PID may belong to someone else by now.
+            let restarting = entry.state == RunState::Restarting;
+            entry.state = end_state(&exit);
+            entry.finished_at = Some(Instant::now());
+            entry.stdin = None;
+            entry.limit = None;
+            entry.parked = None;
+            if !restarting {
+                if let Some(mut info) = self.processes.lock().unwrap().remove(&entry.pid) {
+                    info.status = format!("{:?}", entry.state);
+                    info.ended_at = Some(now_ms());
+                    info.exit_code = exit.code;
+                    info.signal = exit.signal;
+                    self.record_history(info);
+                }
+            }
+        }
+        prune_finished(&mut runs, MAX_FINISHED_RUNS);
+        drop(runs);
+        let _ = tx.send(Some(exit));
+    }
+
+    /// A run ended with `exit` and will be started again: record how its
+    /// process ended and show it as restarting for the `restarts`th time.
+    pub fn mark_restarting(&self, exit: &ExitStatus, restarts: u32) {
+        let mut runs = self.runs.lock().unwrap();
+        let Some(entry) = runs.get_mut(&exit.run_id) else {
+            return;
+        };
+        entry.state = RunState::Restarting;
+        entry.stdin = None;
+        entry.limit = None;
+        if let Some(mut info) = self.processes.lock().unwrap().remove(&entry.pid) {
+            let mut ended = info.clone();
+            ended.status = format!("{:?}", end_state(exit));
+            ended.ended_at = Some(now_ms());
+            ended.exit_code = exit.code;
+            ended.signal = exit.signal;
+            self.record_history(ended);
+
+            info.status = "Restarting".to_string();
+            info.restarts = restarts;
+            info.cpu_usage = 0.0;
+            info.mem_usage = 0;
+            info.ports.clear();
+            info.url = None;
+            entry.parked = Some(info);
+        }
+    }
+
+    /// A restarting run is up again as `pid`. Returns false if the run was
+    /// stopped meanwhile; the caller then has to stop `pid` itself.
+    pub fn resume_run(&self, run_id: &str, pid: u32) -> bool {
+        let mut runs = self.runs.lock().unwrap();
+        let Some(entry) = runs.get_mut(run_id) else {
+            return false;
+        };
+        if entry.stop_requested {
+            return false;
+        }
+        if let Some(mut info) = entry.parked.take() {
+            info.pid = pid;
+            info.status = "Running".to_string();
+            info.started_at = now_ms();
+            self.processes.lock().unwrap().insert(pid, info);
+        }
+        entry.pid = pid;
+        entry.state = RunState::Running;
+        true
+    }
+
+    /// Stop every run from being restarted, e.g. before quitting.
+    pub fn stop_all_runs(&self) {
+        for entry in self.runs.lock().unwrap().values_mut() {
+            if matches!(entry.state, RunState::Running | RunState::Restarting) {
+                entry.stop_requested = true;
+                entry.stop.notify_one();
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for every run to finish, i.e. to have flushed its
+    /// log and recorded its exit. Returns whether they all did.
+    pub fn wait_settled(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let live = self
+                .runs
+                .lock()
+                .unwrap()
+                .values()
+                .any(|entry| matches!(entry.state, RunState::Running | RunState::Restarting));
+            if !live {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Managed processes, including runs waiting to restart.
+    pub fn list(&self) -> Vec<ProcessInfo> {
+        let mut list: Vec<ProcessInfo> = self.processes.lock().unwrap().values().cloned().collect();
+        list.extend(self.restarting());
+        list
+    }
+
+    /// Process info of the runs waiting to restart.
+    pub fn restarting(&self) -> Vec<ProcessInfo> {
+        self.runs.lock().unwrap().values().filter_map(|entry| entry.parked.clone()).collect()
+    }
+
+    /// Stop `run_id` from being restarted. Returns its PID if it is running.
+    pub fn stop_run(&self, run_id: &str) -> Result<Option<u32>, String> {
+        let mut runs = self.runs.lock().unwrap();
+        match runs.get_mut(run_id) {
+            Some(entry) if matches!(entry.state, RunState::Running | RunState::Restarting) => {
+                entry.stop_requested = true;
+                entry.stop.notify_one();
+                Ok((entry.state == RunState::Running).then_some(entry.pid))
+            }
+            Some(_) => Err(format!("Run {} has already exited", run_id)),
+            None => Err(format!("Unknown run: {}", run_id)),
+        }
+    }
+
+    /// Wait out a restart `delay`. Returns whether the run was stopped.
+    pub async fn stopped_within(&self, run_id: &str, delay: std::time::Duration) -> bool {
+        let stop = self.runs.lock().unwrap().get(run_id).map(|entry| entry.stop.clone());
+        if let Some(stop) = stop {
+            let _ = tokio::time::timeout(delay, stop.notified()).await;
+        }
+        self.stop_requested(run_id)
+    }
+
+    pub fn stop_requested(&self, run_id: &str) -> bool {
+        self.runs.lock().unwrap().get(run_id).is_some_and(|entry| entry.stop_requested)
+    }
+
+    pub fn record_history(&self, info: ProcessInfo) {
+        let mut history = self.history.lock().unwrap();
+        history.push_back(info);
+        while history.len() > MAX_HISTORY {
+            history.pop_front();
+        }
+    }
+
+    /// Exited processes, newest first.
+    pub fn history(&self, limit: usize) -> Vec<ProcessInfo> {
+        self.history.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    pub fn command_status(&self, run_id: &str) -> Option<CommandStatus> {
+        let runs = self.runs.lock().unwrap();
+        let entry = runs.get(run_id)?;
+        let exit = entry.exit.borrow().clone();
+        Some(CommandStatus {
+            run_id: run_id.to_string(),
+            pid: entry.pid,
+            state: entry.state,
+            exit,
+        })
+    }
+
+    /// Wait for a run to exit; returns immediately if it already has.
+    pub async fn wait_run(&self, run_id: &str) -> Result<ExitStatus, String> {
+        let mut rx = self
+            .runs
+            .lock()
+            .unwrap()
+            .get(run_id)
+            .map(|entry| entry.exit.clone())
+            .ok_or_else(|| format!("Unknown run: {}", run_id))?;
+        let exit = rx
+            .wait_for(|exit| exit.is_some())
+            .await
+            .map_err(|_| format!("Run {} ended without an exit status", run_id))?;
+        Ok(exit.clone().unwrap_or_else(|| ExitStatus::unknown(run_id)))
+    }
+}
+
+/// The state a run ends in given how its process exited.
+fn end_state(exit: &ExitStatus) -> RunState {
+    if exit.limit_exceeded.is_some() {
+        RunState::LimitExceeded
+    } else if exit.timed_out.is_some() {
+        RunState::TimedOut
+    } else if exit.signal.is_some() {
+        RunState::Killed
+    } else {
+        RunState::Exited
+    }
+}
+
+/// Drop the oldest finished runs so at most `keep` remain.
+fn prune_finished(runs: &mut HashMap<String, RunEntry>, keep: usize) {
+    let mut finished: Vec<(Instant, String)> = runs
+        .iter()
+        .filter_map(|(id, entry)| entry.finished_at.map(|at| (at, id.clone())))
+        .collect();
+    if finished.len() <= keep {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.iter().take(finished.len() - keep) {
+        runs.remove(id);
+    }
 }
 
 /// Get a list of all managed processes.
 #[tauri::command]
 pub fn get_processes(state: State<ProcessManagerState>) -> Vec<ProcessInfo> {
-    let processes = state.processes.lock().unwrap();
-    processes.values().cloned().collect()
+    state.list()
+}
+
+/// Get processes that have exited, newest first, with how they ended.
+#[tauri::command]
+pub fn get_process_history(state: State<ProcessManagerState>, limit: Option<usize>) -> Vec<ProcessInfo> {
+    state.history(limit.unwrap_or(MAX_HISTORY))
 }
 
 /// Detect if Conda is installed and return its path.
@@ -91,6 +633,7 @@ mod tests {
             status: "running".to_string(),
             cpu_usage: 1.5,
             mem_usage: 1024,
+            ..Default::default()
         };
         let json = serde_json::to_string(&info).expect("Failed to serialize ProcessInfo");
         assert!(json.contains("1234"));
@@ -100,9 +643,7 @@ mod tests {
 
     #[test]
     fn test_process_manager_thread_safety() {
-        let state = ProcessManagerState {
-            processes: Arc::new(Mutex::new(HashMap::new())),
-        };
+        let state = ProcessManagerState::new();
         
         // Simulate thread sharing
         let process_lock = state.processes.clone();
@@ -114,6 +655,7 @@ mod tests {
                 status: "sleeping".to_string(),
                 cpu_usage: 0.0,
                 mem_usage: 0,
+                ..Default::default()
             });
         });
         
@@ -123,6 +665,208 @@ mod tests {
         assert!(guard.contains_key(&1));
     }
 
+    fn info(pid: u32) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            name: "test".to_string(),
+            status: "Running".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_lifecycle() {
+        let state = ProcessManagerState::new();
+        let tx = state.register_run("run-1", info(42));
+        assert_eq!(state.command_status("run-1").unwrap().pid, 42);
+        assert_eq!(state.command_status("run-1").unwrap().state, RunState::Running);
+
+        let exit = ExitStatus {
+            run_id: "run-1".to_string(),
+            code: Some(0),
+            signal: None,
+            success: true,
+            timed_out: None,
+            limit_exceeded: None,
+            error: None,
+        };
+        state.finish_run(exit.clone(), &tx);
+
+        assert_eq!(state.wait_run("run-1").await, Ok(exit));
+        assert_eq!(state.command_status("run-1").unwrap().state, RunState::Exited);
+        assert!(state.stop_run("run-1").is_err());
+        assert!(!state.processes.lock().unwrap().contains_key(&42));
+        assert!(state.wait_run("run-missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stdin_write_and_close() {
+        use tokio::io::AsyncReadExt;
+
+        let state = ProcessManagerState::new();
+        let _tx = state.register_run("run-1", info(7));
+        let (writer, mut reader) = tokio::io::duplex(64);
+        state.attach_stdin("run-1", writer);
+
+        state.write_stdin("run-1", b"y\n").await.unwrap();
+        state.close_stdin("run-1").unwrap();
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).await.unwrap();
+        assert_eq!(received, "y\n");
+        assert!(state.write_stdin("run-1", b"n\n").await.is_err());
+        assert!(state.write_stdin("run-missing", b"n\n").await.is_err());
+    }
+
+    #[test]
+    fn test_timed_out_run_state() {
+        let state = ProcessManagerState::new();
+        let tx = state.register_run("run-1", info(9));
+        let exit = ExitStatus::unknown("run-1").timed_out(TimeoutKind::Idle, "No output for 10 ms".to_string());
+        state.finish_run(exit, &tx);
+
+        let status = state.command_status("run-1").unwrap();
+        assert_eq!(status.state, RunState::TimedOut);
+        let json = serde_json::to_string(&status.exit).unwrap();
+        assert!(json.contains("\"timed_out\":\"idle\""));
+    }
+
+    #[tokio::test]
+    async fn test_run_output_outlives_pruning() {
+        let dir = crate::tests::temp_dir("output");
+        let state = ProcessManagerState::new();
+        let _tx = state.register_run("run-1", info(11));
+        let log = Arc::new(RunLog::create(&dir, "run-1").unwrap());
+        state.attach_log("run-1", log.clone());
+        log.append("stdout", "hello".to_string(), false);
+        log.append("stderr", "oops".to_string(), false);
+
+        let output = state.run_output(&dir, "run-1", 1, 10).unwrap();
+        assert_eq!(output.entries[0].text, "oops");
+        assert_eq!(output.total, 2);
+
+        log.finish().await;
+        state.runs.lock().unwrap().clear();
+        let output = state.run_output(&dir, "run-1", 0, 10).unwrap();
+        assert_eq!(output.entries.len(), 2);
+        assert!(state.run_output(&dir, "run-2", 0, 10).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_exited_processes_move_to_history() {
+        let state = ProcessManagerState::new();
+        let tx = state.register_run("run-1", info(5));
+        let exit = ExitStatus {
+            code: Some(1),
+            ..ExitStatus::unknown("run-1")
+        };
+        state.finish_run(exit, &tx);
+
+        let history = state.history(10);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, "Exited");
+        assert_eq!(history[0].exit_code, Some(1));
+        assert!(history[0].ended_at.is_some());
+        assert!(state.processes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_redact_env() {
+        let env: HashMap<String, String> = [
+            ("HF_TOKEN", "hf_abcdefghijklmnopqrstuvwxyz"),
+            ("OPENAI_API_KEY", "sk-x"),
+            ("MODEL_URL", "https://example.com/m?token=abc"),
+            ("PORT", "7860"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let shown = redact_env(&env);
+        assert_eq!(shown["HF_TOKEN"], REDACTED);
+        assert_eq!(shown["OPENAI_API_KEY"], REDACTED);
+        assert_eq!(shown["MODEL_URL"], "https://example.com/m?token=[redacted]");
+        assert_eq!(shown["PORT"], "7860");
+    }
+
+    #[test]
+    fn test_app_for_path() {
+        let apps = Path::new("/home/me/pinokio/api");
+        assert_eq!(
+            app_for_path(apps, Path::new("/home/me/pinokio/api/comfyui.git/app")),
+            Some("comfyui.git".to_string())
+        );
+        assert_eq!(app_for_path(apps, Path::new("/tmp")), None);
+    }
+
+    #[test]
+    fn test_restart_keeps_run_and_counts() {
+        let state = ProcessManagerState::new();
+        let tx = state.register_run("run-1", info(5));
+        let crashed = ExitStatus {
+            code: Some(1),
+            ..ExitStatus::unknown("run-1")
+        };
+        state.mark_restarting(&crashed, 1);
+        assert!(state.processes.lock().unwrap().is_empty());
+        assert_eq!(state.list()[0].status, "Restarting");
+        assert_eq!(state.command_status("run-1").unwrap().state, RunState::Restarting);
+
+        assert!(state.resume_run("run-1", 6));
+        assert_eq!(state.command_status("run-1").unwrap().pid, 6);
+        assert_eq!(state.processes.lock().unwrap()[&6].restarts, 1);
+        assert_eq!(state.stop_run("run-1"), Ok(Some(6)));
+        assert!(state.stop_requested("run-1"));
+
+        state.finish_run(ExitStatus::unknown("run-1"), &tx);
+        let history = state.history(10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].exit_code, Some(1));
+        assert!(state.processes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stopped_run_is_not_resumed() {
+        let state = ProcessManagerState::new();
+        let tx = state.register_run("run-1", info(5));
+        state.mark_restarting(&ExitStatus::unknown("run-1"), 1);
+        state.stop_all_runs();
+        assert!(!state.resume_run("run-1", 6));
+        assert!(state.list().iter().all(|info| info.pid != 6));
+
+        state.finish_run(ExitStatus::unknown("run-1"), &tx);
+        assert!(state.list().is_empty());
+        assert_eq!(state.history(10).len(), 1);
+    }
+
+    #[test]
+    fn test_wait_settled() {
+        let state = ProcessManagerState::new();
+        let tx = state.register_run("run-1", info(5));
+        assert!(!state.wait_settled(Duration::from_millis(50)));
+
+        let finisher = state.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            finisher.finish_run(ExitStatus::unknown("run-1"), &tx);
+        });
+        assert!(state.wait_settled(Duration::from_secs(5)));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_finished_runs_are_pruned() {
+        let state = ProcessManagerState::new();
+        for i in 0..(MAX_FINISHED_RUNS as u32 + 5) {
+            let run_id = format!("run-{}", i);
+            let tx = state.register_run(&run_id, info(i + 1));
+            state.finish_run(ExitStatus::unknown(&run_id), &tx);
+        }
+        let runs = state.runs.lock().unwrap();
+        assert_eq!(runs.len(), MAX_FINISHED_RUNS);
+        assert!(!runs.contains_key("run-0"));
+    }
+
     #[test]
     fn test_detect_conda_smoke() {
         // This test ensures the function doesn't panic regardless of the environment.
@@ -0,0 +1,240 @@
+//! PTY-backed terminal sessions for Pinokio.
+//! Hosts interactive shells (conda prompts, REPLs, progress bars) that need a
+//! real TTY instead of piped stdout/stderr.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use tauri::{State, Window};
+
+type PtyReader = Box<dyn Read + Send>;
+type PtyChild = Box<dyn Child + Send + Sync>;
+/// Write end of a session, locked on its own so a write blocked on a full
+/// PTY doesn't hold up the other sessions.
+type PtyWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+pub struct PtySession {
+    master: Box<dyn MasterPty + Send>,
+    writer: PtyWriter,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    pub pid: Option<u32>,
+}
+
+#[derive(Default)]
+pub struct PtyState {
+    pub sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+}
+
+#[derive(Deserialize)]
+pub struct PtyOptions {
+    pub cols: u16,
+    pub rows: u16,
+    pub cwd: Option<String>,
+    pub env: Option<HashMap<String, String>>,
+    /// Program to run; defaults to the user's shell.
+    pub shell: Option<String>,
+    pub args: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PtyOpened {
+    pub session_id: String,
+    pub pid: Option<u32>,
+}
+
+/// Payload of `pty:data:<session_id>`: raw bytes as written by the program.
+#[derive(Serialize, Clone)]
+pub struct PtyData {
+    pub session_id: String,
+    pub data: Vec<u8>,
+}
+
+/// Payload of `pty:exit:<session_id>`.
+#[derive(Serialize, Clone)]
+pub struct PtyExit {
+    pub session_id: String,
+    pub code: Option<u32>,
+}
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The user's login shell, falling back to a platform default.
+pub fn default_shell() -> String {
+    #[cfg(windows)]
+    {
+        std::env::var("COMSPEC").unwrap_or_else(|_| "powershell.exe".to_string())
+    }
+    #[cfg(not(windows))]
+    {
+        std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string())
+    }
+}
+
+fn pty_size(cols: u16, rows: u16) -> PtySize {
+    PtySize {
+        rows: rows.max(1),
+        cols: cols.max(1),
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Open a PTY and start `command` on it. Returns the session, a reader for
+/// its output and the child to wait on.
+pub fn open_session(
+    command: CommandBuilder,
+    cols: u16,
+    rows: u16,
+) -> Result<(PtySession, PtyReader, PtyChild), String> {
+    let pair = native_pty_system()
+        .openpty(pty_size(cols, rows))
+        .map_err(|e| format!("Failed to open PTY: {}", e))?;
+    let child = pair
+        .slave
+        .spawn_command(command)
+        .map_err(|e| format!("Failed to spawn in PTY: {}", e))?;
+    // Only the child should hold the slave side, so EOF arrives when it exits.
+    drop(pair.slave);
+
+    let reader = pair
+        .master
+        .try_clone_reader()
+        .map_err(|e| format!("Failed to read PTY: {}", e))?;
+    let writer = pair
+        .master
+        .take_writer()
+        .map_err(|e| format!("Failed to write PTY: {}", e))?;
+    let session = PtySession {
+        master: pair.master,
+        writer: Arc::new(Mutex::new(writer)),
+        killer: child.clone_killer(),
+        pid: child.process_id(),
+    };
+    Ok((session, reader, child))
+}
+
+/// Open an interactive PTY session.
+///
+/// Output is emitted as raw bytes on `pty:data:<session_id>`, followed by
+/// `pty:exit:<session_id>` when the program ends.
+#[tauri::command]
+pub fn pty_open(window: Window, state: State<PtyState>, options: PtyOptions) -> Result<PtyOpened, String> {
+    let mut command = CommandBuilder::new(options.shell.unwrap_or_else(default_shell));
+    command.args(options.args.unwrap_or_default());
+    if let Some(dir) = options.cwd {
+        command.cwd(dir);
+    }
+    command.env("TERM", "xterm-256color");
+    for (key, value) in options.env.unwrap_or_default() {
+        command.env(key, value);
+    }
+
+    let (session, mut reader, mut child) = open_session(command, options.cols, options.rows)?;
+    let session_id = format!("pty-{}", NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed));
+    let pid = session.pid;
+    state.sessions.lock().unwrap().insert(session_id.clone(), session);
+
+    let sessions = state.sessions.clone();
+    let id = session_id.clone();
+    std::thread::spawn(move || {
+        let data_event = format!("pty:data:{}", id);
+        let mut buf = [0u8; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let payload = PtyData {
+                        session_id: id.clone(),
+                        data: buf[..n].to_vec(),
+                    };
+                    let _ = window.emit(&data_event, payload);
+                }
+            }
+        }
+        let code = child.wait().ok().map(|status| status.exit_code());
+        sessions.lock().unwrap().remove(&id);
+        let _ = window.emit(&format!("pty:exit:{}", id), PtyExit { session_id: id.clone(), code });
+    });
+
+    Ok(PtyOpened { session_id, pid })
+}
+
+/// Write input (keystrokes, pasted text) to a PTY session.
+#[tauri::command]
+pub async fn pty_write(state: State<'_, PtyState>, session_id: String, data: String) -> Result<(), String> {
+    let writer = state
+        .sessions
+        .lock()
+        .unwrap()
+        .get(&session_id)
+        .map(|session| session.writer.clone())
+        .ok_or_else(|| format!("Unknown PTY session: {}", session_id))?;
+    // Blocks while the program isn't reading, so keep it off the IPC thread.
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut writer = writer.lock().unwrap();
+        writer
+            .write_all(data.as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("PTY write failed: {}", e))
+    })
+    .await
+    .map_err(|e| format!("PTY write failed: {}", e))?
+}
+
+/// Resize a PTY session to the terminal view's size.
+#[tauri::command]
+pub fn pty_resize(state: State<PtyState>, session_id: String, cols: u16, rows: u16) -> Result<(), String> {
+    let sessions = state.sessions.lock().unwrap();
+    let session = sessions
+        .get(&session_id)
+        .ok_or_else(|| format!("Unknown PTY session: {}", session_id))?;
+    session
+        .master
+        .resize(pty_size(cols, rows))
+        .map_err(|e| format!("PTY resize failed: {}", e))
+}
+
+/// Kill the program in a PTY session and close it.
+#[tauri::command]
+pub fn pty_close(state: State<PtyState>, session_id: String) -> Result<(), String> {
+    let mut session = state
+        .sessions
+        .lock()
+        .unwrap()
+        .remove(&session_id)
+        .ok_or_else(|| format!("Unknown PTY session: {}", session_id))?;
+    // The child may already be gone; dropping the master closes the PTY anyway.
+    let _ = session.killer.kill();
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pty_session_is_a_tty() {
+        let mut command = CommandBuilder::new("sh");
+        command.args(["-c", "test -t 1 && echo is-a-tty"]);
+        let (session, mut reader, mut child) = open_session(command, 80, 24).expect("Failed to open PTY");
+        assert!(session.pid.is_some());
+
+        let mut output = Vec::new();
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = reader.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+            if String::from_utf8_lossy(&output).contains("is-a-tty") {
+                break;
+            }
+        }
+        assert!(String::from_utf8_lossy(&output).contains("is-a-tty"));
+        assert!(child.wait().unwrap().success());
+    }
+}
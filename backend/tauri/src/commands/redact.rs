@@ -0,0 +1,161 @@
+//! Secret redaction for anything Pinokio shows or exports.
+//! Masks values under secret-looking keys and well-known token formats, in
+//! free text and in JSON.
+
+use serde_json::Value;
+
+pub const REDACTED: &str = "[redacted]";
+/// Key fragments that mark a value as secret.
+const SECRET_WORDS: &[&str] = &[
+    "token",
+    "secret",
+    "password",
+    "passwd",
+    "apikey",
+    "api_key",
+    "authorization",
+    "cookie",
+    "credential",
+    "private_key",
+];
+/// Keys that are secret when they are, or end in, one of these words.
+const SECRET_SUFFIXES: &[&str] = &["key", "auth"];
+/// Prefixes of well-known access tokens (Hugging Face, OpenAI, GitHub, Slack).
+const TOKEN_PREFIXES: &[&str] = &["hf_", "sk-", "ghp_", "gho_", "github_pat_", "xoxb-", "xoxp-"];
+
+/// Whether the value under `key` should be kept secret.
+pub fn is_secret(key: &str) -> bool {
+    let key = key
+        .trim_matches(|c: char| !c.is_alphanumeric() && c != '_')
+        .to_ascii_lowercase();
+    SECRET_WORDS.iter().any(|word| key.contains(word))
+        || SECRET_SUFFIXES.iter().any(|word| {
+            key == *word || key.ends_with(&format!("_{}", word)) || key.ends_with(&format!("-{}", word))
+        })
+}
+
+fn looks_like_token(word: &str) -> bool {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+    word.len() >= 20 && TOKEN_PREFIXES.iter().any(|prefix| word.starts_with(prefix))
+}
+
+/// Mask `name=value` pairs with a secret name, including in query strings.
+fn redact_pairs(word: &str) -> String {
+    word.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value))
+                if !value.is_empty() && is_secret(name.rsplit(['?', ',', ';']).next().unwrap_or(name)) =>
+            {
+                format!("{}={}", name, REDACTED)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Mask secrets in free text: `KEY=value`, `key: value`, bearer tokens and
+/// well-known token formats.
+pub fn redact_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut secret_next = false;
+    for piece in text.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end();
+        if word.is_empty() {
+            out.push_str(piece);
+            continue;
+        }
+        if secret_next || looks_like_token(word) {
+            out.push_str(REDACTED);
+        } else {
+            out.push_str(&redact_pairs(word));
+        }
+        out.push_str(&piece[word.len()..]);
+        secret_next = word.eq_ignore_ascii_case("bearer")
+            || word.strip_suffix(':').is_some_and(is_secret)
+            || (word.starts_with('-') && !word.contains('=') && is_secret(word));
+    }
+    out
+}
+
+/// Mask secrets in `value`: values under secret keys, flag values in argv
+/// style arrays, and anything `redact_text` catches in strings.
+pub fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret(key) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => {
+            let mut secret_next = false;
+            for item in items.iter_mut() {
+                let flag = item
+                    .as_str()
+                    .is_some_and(|s| s.starts_with('-') && !s.contains('=') && is_secret(s));
+                if secret_next && item.is_string() {
+                    *item = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(item);
+                }
+                secret_next = flag;
+            }
+        }
+        Value::String(text) => *text = redact_text(text),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_secret() {
+        for key in ["HF_TOKEN", "OPENAI_API_KEY", "password", "Authorization", "--hf-token", "\"key\"", "basic_auth"] {
+            assert!(is_secret(key), "{}", key);
+        }
+        for key in ["PATH", "author", "keyboard", "monkey_patch", "cwd"] {
+            assert!(!is_secret(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_redact_text() {
+        assert_eq!(
+            redact_text("HF_TOKEN=abc123 PORT=42000"),
+            "HF_TOKEN=[redacted] PORT=42000"
+        );
+        assert_eq!(
+            redact_text("GET /api?user=me&token=abc Authorization: Bearer xyz"),
+            "GET /api?user=me&token=[redacted] Authorization: [redacted] [redacted]"
+        );
+        assert_eq!(redact_text("\"password\": \"hunter2\"\n"), "\"password\": [redacted]\n");
+        assert_eq!(
+            redact_text("using hf_abcdefghijklmnopqrstuvwx now"),
+            "using [redacted] now"
+        );
+        assert_eq!(redact_text("python app.py --api-key sk1 --port 7860"), "python app.py --api-key [redacted] --port 7860");
+    }
+
+    #[test]
+    fn test_redact_json() {
+        let mut value = json!({
+            "env": { "OPENAI_API_KEY": "sk-x", "PATH": "/usr/bin" },
+            "argv": ["python", "app.py", "--token", "abc", "--share"],
+            "token": null,
+            "cwd": "/home/me/pinokio/api/app?secret=1",
+        });
+        redact_json(&mut value);
+        assert_eq!(value["env"]["OPENAI_API_KEY"], REDACTED);
+        assert_eq!(value["env"]["PATH"], "/usr/bin");
+        assert_eq!(value["argv"], json!(["python", "app.py", "--token", REDACTED, "--share"]));
+        assert!(value["token"].is_null());
+        assert_eq!(value["cwd"], "/home/me/pinokio/api/app?secret=[redacted]");
+    }
+}
@@ -0,0 +1,105 @@
+//! Restart policies for commands run by Pinokio.
+//! Decides whether a run that ended is started again and how long to wait
+//! before doing so.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::process_manager::ExitStatus;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// Restart when the run ends unsuccessfully.
+    OnFailure,
+    /// Restart whenever the run ends.
+    Always,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RestartOptions {
+    pub policy: RestartPolicy,
+    /// Restarts allowed before giving up; `null` for no limit.
+    pub max_retries: Option<u32>,
+    /// Delay before the first restart; doubled for each one after.
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartOptions {
+    fn default() -> Self {
+        Self {
+            policy: RestartPolicy::Never,
+            max_retries: Some(5),
+            backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl RestartOptions {
+    /// Whether a run that ended with `exit` after `restarts` restarts goes again.
+    /// Runs stopped with `kill_command` are never restarted; callers check that.
+    pub fn should_restart(&self, exit: &ExitStatus, restarts: u32) -> bool {
+        if self.max_retries.is_some_and(|max| restarts >= max) {
+            return false;
+        }
+        match self.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !exit.success,
+            RestartPolicy::Always => true,
+        }
+    }
+
+    /// Delay before restart number `restart` (starting at 1).
+    pub fn backoff(&self, restart: u32) -> Duration {
+        let factor = 1u64 << restart.saturating_sub(1).min(20);
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor).min(self.max_backoff_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(success: bool) -> ExitStatus {
+        ExitStatus {
+            code: Some(if success { 0 } else { 1 }),
+            success,
+            ..ExitStatus::unknown("run-1")
+        }
+    }
+
+    #[test]
+    fn test_should_restart() {
+        let never = RestartOptions::default();
+        assert!(!never.should_restart(&exit(false), 0));
+
+        let on_failure = RestartOptions {
+            policy: RestartPolicy::OnFailure,
+            max_retries: Some(2),
+            ..Default::default()
+        };
+        assert!(on_failure.should_restart(&exit(false), 1));
+        assert!(!on_failure.should_restart(&exit(true), 0));
+        assert!(!on_failure.should_restart(&exit(false), 2));
+
+        let always: RestartOptions = serde_json::from_str(r#"{"policy": "always", "max_retries": null}"#).unwrap();
+        assert!(always.should_restart(&exit(true), 1000));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let options = RestartOptions {
+            backoff_ms: 500,
+            max_backoff_ms: 3000,
+            ..Default::default()
+        };
+        let delays: Vec<u64> = (1..=5).map(|n| options.backoff(n).as_millis() as u64).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+    }
+}
@@ -0,0 +1,324 @@
+//! Per-run output history for Pinokio.
+//! Keeps each run's recent output in memory and all of it in a log file
+//! under the data dir, so the UI can replay it after a reload.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::ownership;
+use crate::logging;
+
+/// Entries per run kept in memory; older ones are read back from the file.
+const MAX_MEMORY_ENTRIES: usize = 5000;
+/// Run log files kept on disk; the oldest are removed beyond this.
+const MAX_LOG_FILES: usize = 200;
+/// Entries returned by one `get_run_output` call when no limit is given.
+pub const DEFAULT_READ_LIMIT: usize = 1000;
+
+/// One piece of a run's output.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub seq: u64,
+    /// `stdout` or `stderr`.
+    pub stream: String,
+    pub text: String,
+    /// The line overwrites the previous one (`\r` progress output).
+    pub replace: bool,
+    /// Milliseconds since the Unix epoch.
+    pub at: u64,
+}
+
+/// Result of `get_run_output`.
+#[derive(Serialize, Clone, Debug)]
+pub struct RunOutput {
+    pub run_id: String,
+    pub entries: Vec<LogEntry>,
+    /// Offset to ask for next to continue reading.
+    pub next_offset: u64,
+    /// Entries recorded so far.
+    pub total: u64,
+}
+
+pub fn log_dir() -> PathBuf {
+    ownership::data_dir().join("runs")
+}
+
+/// Run ids become file names, so only accept the ones we generate.
+fn is_valid_run_id(run_id: &str) -> bool {
+    !run_id.is_empty() && run_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+pub fn log_path(dir: &Path, run_id: &str) -> Result<PathBuf, String> {
+    if !is_valid_run_id(run_id) {
+        return Err(format!("Invalid run id: {}", run_id));
+    }
+    Ok(dir.join(format!("{}.log", run_id)))
+}
+
+/// Remove the oldest log and output spill files so at most `keep` remain.
+fn prune_logs(dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut logs: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "log" || ext == "spill"))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    if logs.len() <= keep {
+        return;
+    }
+    logs.sort();
+    for (_, path) in logs.iter().take(logs.len() - keep) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Read entries from `offset` out of a log file.
+pub fn read_file(path: &Path, offset: u64, limit: usize) -> Result<(Vec<LogEntry>, u64), String> {
+    let file = File::open(path).map_err(|e| format!("Read run log failed: {}", e))?;
+    let mut entries = Vec::new();
+    let mut total = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("Read run log failed: {}", e))?;
+        let Ok(entry) = serde_json::from_str::<LogEntry>(&line) else {
+            continue;
+        };
+        total = entry.seq + 1;
+        if entry.seq >= offset && entries.len() < limit {
+            entries.push(entry);
+        }
+    }
+    Ok((entries, total))
+}
+
+/// Work for a run log's writer thread.
+enum WriteOp {
+    Line(String),
+    /// Flush, then call back.
+    Flush(Box<dyn FnOnce() + Send>),
+}
+
+/// Create the private runs dir and `path` in it, then write what arrives
+/// on the returned channel until it is closed. Keeps file IO off the tasks
+/// that stream output.
+fn spawn_writer(dir: PathBuf, path: PathBuf) -> mpsc::Sender<WriteOp> {
+    let (tx, rx) = mpsc::channel::<WriteOp>();
+    std::thread::spawn(move || {
+        let mut writer = fs::create_dir_all(&dir)
+            .and_then(|_| {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let _ = fs::set_permissions(&dir, fs::Permissions::from_mode(0o700));
+                }
+                logging::open_private(&path)
+            })
+            .map(BufWriter::new)
+            .ok();
+        prune_logs(&dir, MAX_LOG_FILES);
+        for op in rx {
+            match op {
+                WriteOp::Line(line) => {
+                    if let Some(writer) = writer.as_mut() {
+                        let _ = writeln!(writer, "{}", line);
+                    }
+                }
+                WriteOp::Flush(done) => {
+                    if let Some(writer) = writer.as_mut() {
+                        let _ = writer.flush();
+                    }
+                    done();
+                }
+            }
+        }
+        if let Some(mut writer) = writer {
+            let _ = writer.flush();
+        }
+    });
+    tx
+}
+
+struct LogInner {
+    recent: VecDeque<LogEntry>,
+    next_seq: u64,
+    writer: Option<mpsc::Sender<WriteOp>>,
+}
+
+/// Output history of one run.
+pub struct RunLog {
+    path: PathBuf,
+    inner: Mutex<LogInner>,
+}
+
+impl RunLog {
+    /// Start the log for `run_id` in `dir`, readable only by the owner.
+    /// Output is still kept in memory if the file cannot be created.
+    pub fn create(dir: &Path, run_id: &str) -> Result<Self, String> {
+        let path = log_path(dir, run_id)?;
+        let writer = spawn_writer(dir.to_path_buf(), path.clone());
+        Ok(Self {
+            path,
+            inner: Mutex::new(LogInner {
+                recent: VecDeque::new(),
+                next_seq: 0,
+                writer: Some(writer),
+            }),
+        })
+    }
+
+    /// Wait until everything appended so far is in the file.
+    fn flush(&self) {
+        let Some(writer) = self.inner.lock().unwrap().writer.clone() else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel();
+        let done = Box::new(move || {
+            let _ = tx.send(());
+        });
+        if writer.send(WriteOp::Flush(done)).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+
+    pub fn append(&self, stream: &str, text: String, replace: bool) {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut inner = self.inner.lock().unwrap();
+        let entry = LogEntry {
+            seq: inner.next_seq,
+            stream: stream.to_string(),
+            text,
+            replace,
+            at,
+        };
+        inner.next_seq += 1;
+        if let Some(writer) = inner.writer.as_ref() {
+            let _ = writer.send(WriteOp::Line(serde_json::to_string(&entry).unwrap_or_default()));
+        }
+        inner.recent.push_back(entry);
+        if inner.recent.len() > MAX_MEMORY_ENTRIES {
+            inner.recent.pop_front();
+        }
+    }
+
+    /// Close the file once the run has ended, resolving when all output is
+    /// written.
+    pub async fn finish(&self) {
+        let Some(writer) = self.inner.lock().unwrap().writer.take() else {
+            return;
+        };
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let done = Box::new(move || {
+            let _ = tx.send(());
+        });
+        if writer.send(WriteOp::Flush(done)).is_ok() {
+            let _ = rx.await;
+        }
+    }
+
+    /// Up to `limit` entries starting at `offset`.
+    pub fn read(&self, run_id: &str, offset: u64, limit: usize) -> Result<RunOutput, String> {
+        let inner = self.inner.lock().unwrap();
+        let total = inner.next_seq;
+        let oldest = inner.recent.front().map(|e| e.seq).unwrap_or(total);
+        let entries = if offset >= oldest {
+            inner
+                .recent
+                .iter()
+                .skip((offset - oldest) as usize)
+                .take(limit)
+                .cloned()
+                .collect()
+        } else {
+            // Older than what is kept in memory: go to the file.
+            drop(inner);
+            self.flush();
+            read_file(&self.path, offset, limit)?.0
+        };
+        Ok(RunOutput {
+            run_id: run_id.to_string(),
+            next_offset: entries.last().map(|e| e.seq + 1).unwrap_or(offset.max(total)),
+            entries,
+            total,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+
+    #[tokio::test]
+    async fn test_read_from_memory_and_file() {
+        let dir = temp_dir("runlog-read");
+        let log = RunLog::create(&dir, "run-1").unwrap();
+        for i in 0..(MAX_MEMORY_ENTRIES + 10) {
+            log.append("stdout", format!("line {}", i), false);
+        }
+
+        let recent = log.read("run-1", (MAX_MEMORY_ENTRIES + 8) as u64, 10).unwrap();
+        assert_eq!(recent.entries.len(), 2);
+        assert_eq!(recent.total, (MAX_MEMORY_ENTRIES + 10) as u64);
+        assert_eq!(recent.next_offset, recent.total);
+
+        let old = log.read("run-1", 3, 2).unwrap();
+        let texts: Vec<&str> = old.entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, vec!["line 3", "line 4"]);
+        assert_eq!(old.next_offset, 5);
+
+        log.finish().await;
+        let (entries, total) = read_file(&log_path(&dir, "run-1").unwrap(), 0, 1).unwrap();
+        assert_eq!(entries[0].text, "line 0");
+        assert_eq!(total, (MAX_MEMORY_ENTRIES + 10) as u64);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_log_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("runlog-private");
+        let log = RunLog::create(&dir, "run-1").unwrap();
+        log.append("stdout", "HF_TOKEN=abc".to_string(), false);
+        log.finish().await;
+        let mode = fs::metadata(log_path(&dir, "run-1").unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_log_path_rejects_traversal() {
+        let dir = PathBuf::from("/tmp");
+        assert!(log_path(&dir, "run-18c-1").is_ok());
+        assert!(log_path(&dir, "../etc/passwd").is_err());
+        assert!(log_path(&dir, "").is_err());
+    }
+
+    #[test]
+    fn test_prune_logs_keeps_newest() {
+        let dir = temp_dir("runlog-prune");
+        fs::write(dir.join("run-0.stdout.spill"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        for i in 0..5 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            fs::write(dir.join(format!("run-{}.log", i)), "").unwrap();
+        }
+        prune_logs(&dir, 2);
+        assert!(!dir.join("run-0.log").exists());
+        assert!(!dir.join("run-0.stdout.spill").exists());
+        assert!(dir.join("run-4.log").exists());
+        assert!(dir.join("notes.txt").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
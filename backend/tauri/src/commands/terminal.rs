@@ -2,95 +2,731 @@
 //! Provides async process spawning with streaming output.
 
 use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
 use tauri::Window;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
 
-/// Execute a command with streaming stdout/stderr to the frontend.
-#[tauri::command]
-pub async fn run_command(
+use super::batch::{BatchOptions, Batcher, Chunk};
+use super::environment::{self, EnvOptions};
+use super::limits::{self, ResourceLimits, Violation};
+use super::monitor;
+use super::output::{LineDecoder, OutputMode, TextDecoder};
+use super::restart::RestartOptions;
+use super::process_manager::{self, CommandStatus, ExitStatus, ProcessInfo, ProcessManagerState, TimeoutKind};
+use super::run_log::{self, RunLog, RunOutput, DEFAULT_READ_LIMIT};
+use crate::signal::{self, Signal};
+
+/// Payload of `terminal:stdout:<channel>` and `terminal:stderr:<channel>`
+/// in `lines` mode.
+#[derive(Serialize, Clone)]
+pub struct OutputLine {
+    pub run_id: String,
+    pub line: String,
+    /// The line came after a `\r`: overwrite the last line shown.
+    pub replace: bool,
+}
+
+/// Output payload in `text` mode.
+#[derive(Serialize, Clone)]
+pub struct OutputText {
+    pub run_id: String,
+    pub text: String,
+}
+
+/// Output payload in `bytes` mode.
+#[derive(Serialize, Clone)]
+pub struct OutputBytes {
+    pub run_id: String,
+    pub data: Vec<u8>,
+}
+
+/// Returned by `spawn_command`.
+#[derive(Serialize, Clone)]
+pub struct SpawnedCommand {
+    pub run_id: String,
+    pub pid: u32,
+}
+
+/// Payload of `terminal:restart:<channel>` and `terminal:restart`.
+#[derive(Serialize, Clone)]
+pub struct RestartNotice {
+    pub run_id: String,
+    /// Which restart this is, starting at 1.
+    pub restarts: u32,
+    pub max_retries: Option<u32>,
+    pub delay_ms: u64,
+    /// How the process that is being replaced ended.
+    pub exit: ExitStatus,
+}
+
+/// Extra options for `run_command` and `spawn_command`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RunOptions {
+    /// `env`, `clear_env`, `venv` and `conda_env`, at the top level.
+    #[serde(flatten)]
+    pub environment: EnvOptions,
+    /// Terminate the run after this long.
+    pub timeout_ms: Option<u64>,
+    /// Terminate the run after this long without any output.
+    pub idle_timeout_ms: Option<u64>,
+    /// `lines` (default), `text` or `bytes`.
+    pub output: OutputMode,
+    /// Coalesce output into `OutputFrame`s instead of one event per chunk.
+    pub batch: Option<BatchOptions>,
+    /// Pinokio app the command belongs to; inferred from `cwd` if unset.
+    pub app: Option<String>,
+    pub limits: ResourceLimits,
+    pub restart: RestartOptions,
+}
+
+impl RunOptions {
+    /// `timeout_ms`, or `limits.max_runtime_ms` if that is shorter.
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms
+            .into_iter()
+            .chain(self.limits.max_runtime_ms)
+            .min()
+            .map(Duration::from_millis)
+    }
+}
+
+/// How long a run stopped by Pinokio gets to exit after SIGTERM before
+/// being killed.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// How long output may keep arriving after the leader exited. Processes it
+/// left in the background can hold the pipes open indefinitely.
+const DRAIN_GRACE: Duration = Duration::from_secs(2);
+
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Unique id for one `run_command` invocation.
+fn next_run_id() -> String {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    format!("run-{:x}-{}", started, NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Tauri only accepts alphanumerics, `-`, `/`, `:` and `_` in event names.
+fn is_valid_channel(channel: &str) -> bool {
+    !channel.is_empty()
+        && channel
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '/' | ':' | '_'))
+}
+
+/// Where one of a run's output streams goes.
+struct StreamTarget {
     window: Window,
-    state: tauri::State<'_, super::process_manager::ProcessManagerState>,
+    event: String,
+    run_id: String,
+    /// `stdout` or `stderr`.
+    stream: &'static str,
+    log: Arc<RunLog>,
+    batcher: Option<Batcher>,
+}
+
+impl StreamTarget {
+    /// Record `chunk` in the run log and send it to the frontend.
+    fn deliver(&self, chunk: Chunk) {
+        let (text, replace) = match &chunk {
+            Chunk::Line(line) => (line.text.clone(), line.replace),
+            Chunk::Text(text) => (text.clone(), false),
+            Chunk::Bytes(data) => (String::from_utf8_lossy(data).into_owned(), false),
+        };
+        self.log.append(self.stream, text, replace);
+
+        if let Some(batcher) = &self.batcher {
+            batcher.push(chunk);
+            return;
+        }
+        let run_id = self.run_id.clone();
+        let _ = match chunk {
+            Chunk::Line(line) => self.window.emit(
+                &self.event,
+                OutputLine {
+                    run_id,
+                    line: line.text,
+                    replace: line.replace,
+                },
+            ),
+            Chunk::Text(text) => self.window.emit(&self.event, OutputText { run_id, text }),
+            Chunk::Bytes(data) => self.window.emit(&self.event, OutputBytes { run_id, data }),
+        };
+    }
+}
+
+/// Read a stream to its end, delivering its output to `target` in `mode`.
+/// Whatever is still buffered at end of stream is sent before returning.
+fn stream_output<R>(
+    mut reader: R,
+    target: StreamTarget,
+    mode: OutputMode,
+    activity: Arc<Notify>,
+    mut cut: watch::Receiver<bool>,
+) -> JoinHandle<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let deliver = |chunk: Chunk| target.deliver(chunk);
+        let deliver_text = |text: String| {
+            if !text.is_empty() {
+                deliver(Chunk::Text(text));
+            }
+        };
+
+        let read = async {
+            let mut lines = LineDecoder::default();
+            let mut text = TextDecoder::default();
+            let mut buf = vec![0u8; 8192];
+            loop {
+                let n = tokio::select! {
+                    read = reader.read(&mut buf) => match read {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    },
+                    Ok(_) = cut.wait_for(|cut| *cut) => break,
+                };
+                activity.notify_one();
+                let chunk = &buf[..n];
+                match mode {
+                    OutputMode::Lines => lines.feed(chunk).into_iter().map(Chunk::Line).for_each(deliver),
+                    OutputMode::Text => deliver_text(text.feed(chunk)),
+                    OutputMode::Bytes => deliver(Chunk::Bytes(chunk.to_vec())),
+                }
+                // Let the flusher send a full frame instead of piling up more.
+                if target.batcher.as_ref().is_some_and(Batcher::is_full) {
+                    tokio::task::yield_now().await;
+                }
+            }
+            match mode {
+                OutputMode::Lines => lines.finish().into_iter().map(Chunk::Line).for_each(deliver),
+                OutputMode::Text => deliver_text(text.finish()),
+                OutputMode::Bytes => {}
+            }
+            if let Some(batcher) = &target.batcher {
+                batcher.close();
+            }
+        };
+
+        match &target.batcher {
+            Some(batcher) => {
+                let flush = batcher.flush_frames(|frame| {
+                    let _ = target.window.emit(&target.event, frame);
+                });
+                tokio::join!(read, flush);
+            }
+            None => read.await,
+        }
+    })
+}
+
+/// Resolve once the run has gone `timeout` in total, or `idle` without any
+/// `activity`. Never resolves if neither is set.
+async fn timeout_expired(timeout: Option<Duration>, idle: Option<Duration>, activity: &Notify) -> TimeoutKind {
+    let total = async {
+        match timeout {
+            Some(limit) => tokio::time::sleep(limit).await,
+            None => std::future::pending().await,
+        }
+    };
+    let quiet = async {
+        match idle {
+            Some(limit) => while tokio::time::timeout(limit, activity.notified()).await.is_ok() {},
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = total => TimeoutKind::Total,
+        _ = quiet => TimeoutKind::Idle,
+    }
+}
+
+/// Why Pinokio ended a run itself.
+enum Stopped {
+    Timeout(TimeoutKind),
+    Limit(Violation),
+}
+
+/// Everything needed to start a run's process, kept to start it again.
+struct Launch {
     cmd: String,
     args: Vec<String>,
     cwd: Option<String>,
-) -> Result<i32, String> {
-    let mut command = Command::new(&cmd);
-    command.args(&args);
+    options: RunOptions,
+}
+
+impl Launch {
+    fn spawn(&self) -> Result<Child, String> {
+        let mut command = Command::new(&self.cmd);
+        command.args(&self.args);
+
+        environment::apply(&mut command, &self.options.environment, self.cwd.as_deref().map(std::path::Path::new))?;
+        if let Some(dir) = &self.cwd {
+            command.current_dir(dir);
+        }
 
-    if let Some(dir) = cwd {
-        command.current_dir(dir);
+        command.stdin(Stdio::piped());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+        // Lead a new process group so kill_process can take the whole tree.
+        signal::isolate(&mut command);
+        limits::apply(&mut command, &self.options.limits);
+
+        command
+            .spawn()
+            .map_err(|e| format!("Failed to spawn process: {}", e))
     }
+}
+
+/// A registered run, from its first process to its final exit.
+struct Run {
+    window: Window,
+    state: ProcessManagerState,
+    run_id: String,
+    channel: String,
+    log: Arc<RunLog>,
+    launch: Launch,
+}
+
+impl Run {
+    /// Stream one process's output and wait for it to end, stopping it if it
+    /// overstays a timeout or breaks a limit.
+    async fn attempt(&self, mut child: Child) -> ExitStatus {
+        let options = &self.launch.options;
+        let pid = child.id().unwrap_or(0);
+        if let Some(stdin) = child.stdin.take() {
+            self.state.attach_stdin(&self.run_id, stdin);
+        }
+        let (limit_tx, limit_rx) = watch::channel(None);
+        self.state.attach_limits(&self.run_id, limit_tx);
+
+        let activity = Arc::new(Notify::new());
+        let (cut_tx, cut_rx) = watch::channel(false);
+        let target = |stream: &'static str| StreamTarget {
+            window: self.window.clone(),
+            event: format!("terminal:{}:{}", stream, self.channel),
+            run_id: self.run_id.clone(),
+            stream,
+            log: self.log.clone(),
+            batcher: options.batch.clone().map(|batch| {
+                let spill_path = run_log::log_dir().join(format!("{}.{}.spill", self.run_id, stream));
+                Batcher::new(&self.run_id, batch, spill_path)
+            }),
+        };
+        let stdout_task = child
+            .stdout
+            .take()
+            .map(|stdout| stream_output(stdout, target("stdout"), options.output, activity.clone(), cut_rx.clone()));
+        let stderr_task = child
+            .stderr
+            .take()
+            .map(|stderr| stream_output(stderr, target("stderr"), options.output, activity.clone(), cut_rx));
+
+        let timeout = options.timeout();
+        let idle = options.idle_timeout_ms.map(Duration::from_millis);
+        let stopped = tokio::select! {
+            status = child.wait() => Err(status),
+            kind = timeout_expired(timeout, idle, &activity) => Ok(Stopped::Timeout(kind)),
+            violation = limits::exceeded(limit_rx) => Ok(Stopped::Limit(violation)),
+        };
+        let (status, stopped) = match stopped {
+            Err(status) => (status, None),
+            Ok(reason) => {
+                let _ = signal::send(pid, Signal::Term, true);
+                let status = match tokio::time::timeout(STOP_GRACE, child.wait()).await {
+                    Ok(status) => status,
+                    Err(_) => {
+                        let _ = signal::send(pid, Signal::Kill, true);
+                        child.wait().await
+                    }
+                };
+                // Children that outlived the leader would keep the pipes open.
+                let _ = signal::send_group(pid, Signal::Kill);
+                (status, Some(reason))
+            }
+        };
+
+        // Drain output before reporting the exit, but stop reading once the
+        // grace period is over.
+        let mut tasks: Vec<JoinHandle<()>> = [stdout_task, stderr_task].into_iter().flatten().collect();
+        let drain = async {
+            for task in tasks.iter_mut() {
+                let _ = task.await;
+            }
+        };
+        if tokio::time::timeout(DRAIN_GRACE, drain).await.is_err() {
+            cut_tx.send_replace(true);
+            for task in tasks {
+                let _ = task.await;
+            }
+        }
 
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
+        let exit = match status {
+            Ok(status) => ExitStatus::from_std(&self.run_id, &status),
+            Err(_) => ExitStatus::unknown(&self.run_id),
+        };
+        match stopped {
+            Some(Stopped::Timeout(kind)) => {
+                let message = match kind {
+                    TimeoutKind::Total => {
+                        format!("Command timed out after {} ms", timeout.unwrap_or_default().as_millis())
+                    }
+                    TimeoutKind::Idle => {
+                        format!("Command produced no output for {} ms", idle.unwrap_or_default().as_millis())
+                    }
+                };
+                exit.timed_out(kind, message)
+            }
+            Some(Stopped::Limit(violation)) => exit.limit_exceeded(violation),
+            None => exit,
+        }
+    }
 
-    let mut child = command
-        .spawn()
-        .map_err(|e| format!("Failed to spawn process: {}", e))?;
+    /// Run `child` and, as the restart policy says, the processes started
+    /// after it, then report the final exit.
+    async fn supervise(self, mut child: Child, exit_tx: watch::Sender<Option<ExitStatus>>) {
+        let restart = self.launch.options.restart.clone();
+        let mut restarts = 0;
+        let exit = loop {
+            let exit = self.attempt(child).await;
+            if !restart.should_restart(&exit, restarts) || self.state.stop_requested(&self.run_id) {
+                break exit;
+            }
+            restarts += 1;
+            let delay = restart.backoff(restarts);
+            self.state.mark_restarting(&exit, restarts);
+            let notice = RestartNotice {
+                run_id: self.run_id.clone(),
+                restarts,
+                max_retries: restart.max_retries,
+                delay_ms: delay.as_millis() as u64,
+                exit: exit.clone(),
+            };
+            let _ = self.window.emit(&format!("terminal:restart:{}", self.channel), notice.clone());
+            let _ = self.window.emit("terminal:restart", notice);
+
+            if self.state.stopped_within(&self.run_id, delay).await {
+                break exit;
+            }
+            match self.launch.spawn() {
+                Ok(mut next) => {
+                    if !self.state.resume_run(&self.run_id, next.id().unwrap_or(0)) {
+                        // Stopped while spawning; don't leave the new process behind.
+                        if let Some(pid) = next.id() {
+                            let _ = signal::send(pid, Signal::Kill, true);
+                        }
+                        let _ = next.wait().await;
+                        break exit;
+                    }
+                    child = next;
+                }
+                Err(e) => {
+                    break ExitStatus {
+                        error: Some(e),
+                        ..exit
+                    }
+                }
+            }
+        };
+        self.log.finish().await;
+
+        // Unregister process
+        self.state.finish_run(exit.clone(), &exit_tx);
+
+        let _ = self.window.emit(&format!("terminal:exit:{}", self.channel), exit.clone());
+        let _ = self.window.emit("terminal:exit", exit);
+    }
+}
+
+/// Spawn `cmd`, register it and stream its output.
+///
+/// Output is emitted on `terminal:stdout:<channel>` /
+/// `terminal:stderr:<channel>` as lines, text or bytes depending on
+/// `options.output`, where the channel is the caller's `window_id` or else
+/// the run id. A final `terminal:exit` event carries the exit status once
+/// both streams are fully drained.
+///
+/// With `timeout_ms`/`idle_timeout_ms` set, a run that overstays is sent
+/// SIGTERM, then SIGKILL, and ends as `TimedOut` with an `error` message.
+/// A run that breaks its `limits` is stopped the same way and ends as
+/// `LimitExceeded`.
+///
+/// Under a `restart` policy an ended process is started again after a
+/// backoff under the same run id, announced by `terminal:restart:<channel>`.
+fn spawn_run(
+    window: Window,
+    state: &ProcessManagerState,
+    cmd: String,
+    args: Vec<String>,
+    cwd: Option<String>,
+    window_id: Option<String>,
+    options: RunOptions,
+) -> Result<SpawnedCommand, String> {
+    let run_id = next_run_id();
+    let channel = window_id.unwrap_or_else(|| run_id.clone());
+    if !is_valid_channel(&channel) {
+        return Err(format!("Invalid channel id: {}", channel));
+    }
+    let log = Arc::new(RunLog::create(&run_log::log_dir(), &run_id)?);
+
+    let app = options.app.clone().or_else(|| {
+        let dir = cwd.as_deref().map(std::path::PathBuf::from).or_else(|| std::env::current_dir().ok())?;
+        process_manager::app_for_path(&process_manager::apps_dir(), &dir)
+    });
+    let launch = Launch { cmd, args, cwd, options };
+    let child = launch.spawn()?;
 
     let pid = child.id().unwrap_or(0);
-    
+
     // Register process
-    if pid > 0 {
-        let mut processes = state.processes.lock().unwrap();
-        processes.insert(pid, super::process_manager::ProcessInfo {
+    let exit_tx = state.register_run(
+        &run_id,
+        ProcessInfo {
             pid,
-            name: cmd.clone(),
+            name: launch.cmd.clone(),
             status: "Running".to_string(),
-            cpu_usage: 0.0,
-            mem_usage: 0,
-        });
+            run_id: Some(run_id.clone()),
+            argv: std::iter::once(&launch.cmd).chain(&launch.args).cloned().collect(),
+            cwd: launch.cwd.clone(),
+            env: process_manager::redact_env(&launch.options.environment.env),
+            started_at: process_manager::now_ms(),
+            app,
+            limits: launch.options.limits.clone(),
+            restart: launch.options.restart.clone(),
+            ..Default::default()
+        },
+    );
+    state.attach_log(&run_id, log.clone());
+
+    let run = Run {
+        window,
+        state: state.clone(),
+        run_id: run_id.clone(),
+        channel,
+        log,
+        launch,
+    };
+    tokio::spawn(run.supervise(child, exit_tx));
+
+    Ok(SpawnedCommand { run_id, pid })
+}
+
+/// Start a command and stream its output to the frontend.
+/// Returns the run id right away; see `spawn_run` for the events emitted.
+/// `options` sets environment variables and activates a venv or conda env.
+#[tauri::command]
+pub async fn run_command(
+    window: Window,
+    state: tauri::State<'_, ProcessManagerState>,
+    cmd: String,
+    args: Vec<String>,
+    cwd: Option<String>,
+    window_id: Option<String>,
+    options: Option<RunOptions>,
+) -> Result<String, String> {
+    spawn_run(window, &state, cmd, args, cwd, window_id, options.unwrap_or_default())
+        .map(|spawned| spawned.run_id)
+}
+
+/// Start a command without waiting for it and return its run id and PID.
+#[tauri::command]
+pub async fn spawn_command(
+    window: Window,
+    state: tauri::State<'_, ProcessManagerState>,
+    cmd: String,
+    args: Vec<String>,
+    cwd: Option<String>,
+    window_id: Option<String>,
+    options: Option<RunOptions>,
+) -> Result<SpawnedCommand, String> {
+    spawn_run(window, &state, cmd, args, cwd, window_id, options.unwrap_or_default())
+}
+
+/// Wait until a run exits and return its exit status.
+#[tauri::command]
+pub async fn wait_command(
+    state: tauri::State<'_, ProcessManagerState>,
+    run_id: String,
+) -> Result<ExitStatus, String> {
+    state.wait_run(&run_id).await
+}
+
+/// Get whether a run is still going and, if not, how it ended.
+#[tauri::command]
+pub fn get_command_status(
+    state: tauri::State<'_, ProcessManagerState>,
+    run_id: String,
+) -> Result<CommandStatus, String> {
+    state
+        .command_status(&run_id)
+        .ok_or_else(|| format!("Unknown run: {}", run_id))
+}
+
+/// Replay a run's recorded stdout/stderr, e.g. after the window reloads.
+/// Reads up to `limit` entries starting at `offset` (both optional).
+#[tauri::command]
+pub async fn get_run_output(
+    state: tauri::State<'_, ProcessManagerState>,
+    run_id: String,
+    offset: Option<u64>,
+    limit: Option<usize>,
+) -> Result<RunOutput, String> {
+    let state = state.inner().clone();
+    // Waits on the log writer and reads the file, so keep it off the IPC thread.
+    tauri::async_runtime::spawn_blocking(move || {
+        state.run_output(
+            &run_log::log_dir(),
+            &run_id,
+            offset.unwrap_or(0),
+            limit.unwrap_or(DEFAULT_READ_LIMIT),
+        )
+    })
+    .await
+    .map_err(|e| format!("Read run output failed: {}", e))?
+}
+
+/// Signal a running command by run id. Same options as `kill_process`.
+/// This also stops the command from being restarted.
+#[tauri::command]
+pub fn kill_command(
+    state: tauri::State<'_, ProcessManagerState>,
+    run_id: String,
+    signal: Option<String>,
+    tree: Option<bool>,
+) -> Result<(), String> {
+    // A killed run is not restarted; one between restarts just stays down.
+    match state.stop_run(&run_id)? {
+        Some(pid) => send_to(pid, signal, tree),
+        None => Ok(()),
     }
+}
 
-    // Stream stdout (omitted for brevity in logic but maintained in actual code)
-    if let Some(stdout) = child.stdout.take() {
-        let window_clone = window.clone();
-        tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                let _ = window_clone.emit("terminal:stdout", &line);
-            }
-        });
+/// Send input to a running command, e.g. the answer to "Proceed (y/n)?".
+/// Include the trailing newline if the program reads lines.
+#[tauri::command]
+pub async fn write_stdin(
+    state: tauri::State<'_, ProcessManagerState>,
+    run_id: String,
+    data: String,
+) -> Result<(), String> {
+    state.write_stdin(&run_id, data.as_bytes()).await
+}
+
+/// Close a running command's stdin so it sees end-of-file.
+#[tauri::command]
+pub fn close_stdin(state: tauri::State<'_, ProcessManagerState>, run_id: String) -> Result<(), String> {
+    state.close_stdin(&run_id)
+}
+
+fn send_to(pid: u32, signal: Option<String>, tree: Option<bool>) -> Result<(), String> {
+    let signal = match signal {
+        Some(name) => Signal::parse(&name)?,
+        None => Signal::Kill,
+    };
+    if tree.unwrap_or(true) {
+        monitor::signal_tree(pid, signal)
+    } else {
+        signal::send(pid, signal, false)
     }
+}
 
-    // Stream stderr
-    if let Some(stderr) = child.stderr.take() {
-        let window_clone = window.clone();
-        tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                let _ = window_clone.emit("terminal:stderr", &line);
-            }
-        });
+/// Send a signal to a process started by `run_command`.
+///
+/// `signal` is one of TERM, INT, KILL (default) or HUP. With `tree` (the
+/// default) the whole process tree is signalled, including grandchildren:
+/// by process group for commands, and by walking the sampled tree for
+/// processes adopted from the backend.
+/// Like `kill_command`, this stops the command from being restarted.
+#[tauri::command]
+pub fn kill_process(
+    state: tauri::State<'_, ProcessManagerState>,
+    pid: u32,
+    signal: Option<String>,
+    tree: Option<bool>,
+) -> Result<(), String> {
+    let run_id = match state.processes.lock().unwrap().get(&pid) {
+        Some(info) => info.run_id.clone(),
+        None => return Err(format!("Process {} is not managed by Pinokio", pid)),
+    };
+    if let Some(run_id) = run_id {
+        state.stop_run(&run_id)?;
     }
+    send_to(pid, signal, tree)
+}
 
-    let status = child
-        .wait()
-        .await
-        .map_err(|e| format!("Wait failed: {}", e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Unregister process
-    if pid > 0 {
-        let mut processes = state.processes.lock().unwrap();
-        processes.remove(&pid);
+    #[test]
+    fn test_run_ids_are_unique() {
+        let a = next_run_id();
+        let b = next_run_id();
+        assert!(a.starts_with("run-"));
+        assert_ne!(a, b);
+        assert!(is_valid_channel(&a));
     }
 
-    Ok(status.code().unwrap_or(-1))
-}
+    #[test]
+    fn test_channel_validation() {
+        assert!(is_valid_channel("win_abc123"));
+        assert!(is_valid_channel("app/term-1:main"));
+        assert!(!is_valid_channel(""));
+        assert!(!is_valid_channel("bad channel"));
+        assert!(!is_valid_channel("x.y"));
+    }
 
-/// Kill a running process by PID.
-#[tauri::command]
-pub fn kill_process(pid: u32) -> Result<(), String> {
-    #[cfg(unix)]
-    {
-        use std::process::Command as StdCommand;
-        StdCommand::new("kill")
-            .args(["-9", &pid.to_string()])
-            .status()
-            .map_err(|e| format!("Kill failed: {}", e))?;
+    #[test]
+    fn test_env_options_are_flattened() {
+        let options: RunOptions =
+            serde_json::from_str(r#"{"env": {"PORT": "7860"}, "venv": "env", "timeout_ms": 5}"#).unwrap();
+        assert_eq!(options.environment.env["PORT"], "7860");
+        assert_eq!(options.environment.venv.as_deref(), Some("env"));
+        assert_eq!(options.timeout_ms, Some(5));
+    }
+
+    #[test]
+    fn test_max_runtime_is_a_timeout() {
+        let mut options = RunOptions::default();
+        options.limits.max_runtime_ms = Some(500);
+        assert_eq!(options.timeout(), Some(Duration::from_millis(500)));
+        options.timeout_ms = Some(200);
+        assert_eq!(options.timeout(), Some(Duration::from_millis(200)));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_expired() {
+        let activity = Notify::new();
+        let ms = Duration::from_millis;
+        assert_eq!(timeout_expired(Some(ms(20)), None, &activity).await, TimeoutKind::Total);
+        assert_eq!(timeout_expired(None, Some(ms(20)), &activity).await, TimeoutKind::Idle);
+
+        // Regular output keeps the idle timer from firing.
+        let ticker = async {
+            for _ in 0..10 {
+                tokio::time::sleep(ms(10)).await;
+                activity.notify_one();
+            }
+            std::future::pending::<()>().await
+        };
+        let kind = tokio::select! {
+            kind = timeout_expired(Some(ms(80)), Some(ms(50)), &activity) => kind,
+            _ = ticker => unreachable!(),
+        };
+        assert_eq!(kind, TimeoutKind::Total);
     }
-    Ok(())
 }
@@ -0,0 +1,198 @@
+//! Single-instance support for Pinokio.
+//! The primary launcher listens on a loopback socket recorded in the launcher
+//! lock; a second launch forwards its arguments there and exits.
+
+use std::io::{BufRead, BufReader as StdBufReader, Write};
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::backend::ownership;
+
+/// What a second launch hands over to the running instance.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LaunchRequest {
+    pub token: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+}
+
+/// Payload of the `instance:launch` event.
+#[derive(Serialize, Clone)]
+struct LaunchEvent {
+    args: Vec<String>,
+    cwd: Option<String>,
+}
+
+/// Random 128-bit token from the OS, proving a client could read the
+/// (owner-only) lock file.
+pub fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Generate instance token failed: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Bind the handoff socket on an ephemeral loopback port.
+pub fn bind() -> Result<std::net::TcpListener, String> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .map_err(|e| format!("Bind instance socket failed: {}", e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Configure instance socket failed: {}", e))?;
+    Ok(listener)
+}
+
+/// Read one launch request; answers `ok` only when the token matches.
+pub async fn read_request(stream: TcpStream, token: &str) -> Option<LaunchRequest> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    let mut reader = BufReader::new(reader);
+    tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+        .await
+        .ok()?
+        .ok()?;
+
+    let request = serde_json::from_str::<LaunchRequest>(&line)
+        .ok()
+        .filter(|r| r.token == token);
+    let reply: &[u8] = if request.is_some() { b"ok\n" } else { b"denied\n" };
+    let _ = writer.write_all(reply).await;
+    request
+}
+
+fn focus_main(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Accept launch requests for the lifetime of the app, focusing the main
+/// window and emitting `instance:launch` with the forwarded arguments.
+pub async fn serve(app: AppHandle, listener: std::net::TcpListener, token: String) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("instance", "Failed to listen for launches: {}", e);
+            return;
+        }
+    };
+    while let Ok((stream, _)) = listener.accept().await {
+        let Some(request) = read_request(stream, &token).await else {
+            warn!("instance", "Rejected launch request with a bad token");
+            continue;
+        };
+        info!("instance", "Second launch forwarded args: {:?}", request.args);
+        focus_main(&app);
+        let _ = app.emit_all(
+            "instance:launch",
+            LaunchEvent {
+                args: request.args,
+                cwd: request.cwd,
+            },
+        );
+    }
+}
+
+/// Send a launch request to `port` and wait for the acknowledgement.
+pub fn send(port: u16, request: &LaunchRequest) -> Result<(), String> {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let mut stream = StdTcpStream::connect_timeout(&addr, Duration::from_secs(2))
+        .map_err(|e| format!("Connect to running instance failed: {}", e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("Configure socket failed: {}", e))?;
+
+    let mut line = serde_json::to_string(request).map_err(|e| format!("Serialize request failed: {}", e))?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|e| format!("Send to running instance failed: {}", e))?;
+
+    let mut reply = String::new();
+    StdBufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|e| format!("No reply from running instance: {}", e))?;
+    if reply.trim() == "ok" {
+        Ok(())
+    } else {
+        Err(format!("Running instance refused the launch: {}", reply.trim()))
+    }
+}
+
+/// Hand this launch over to the instance that holds the lock at `lock_path`.
+/// The primary may still be starting up, so the socket is retried briefly.
+pub fn forward(lock_path: &Path) -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cwd = std::env::current_dir().ok().map(|p| p.to_string_lossy().to_string());
+    let mut last_err = "Running instance has no launch socket".to_string();
+
+    for _ in 0..10 {
+        let record = ownership::read_record(lock_path).unwrap_or_default();
+        if let (Some(port), Some(token)) = (record.ipc_port, record.token) {
+            let request = LaunchRequest {
+                token,
+                args: args.clone(),
+                cwd: cwd.clone(),
+            };
+            match send(port, &request) {
+                Ok(()) => return Ok(()),
+                Err(e) => last_err = e,
+            }
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    Err(last_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_token_is_unique() {
+        let a = new_token().unwrap();
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, new_token().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_launch_request_roundtrip() {
+        let listener = TcpListener::from_std(bind().unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let request = LaunchRequest {
+            token: "secret".to_string(),
+            args: vec!["pinokio://open/app".to_string()],
+            cwd: None,
+        };
+
+        let sent = request.clone();
+        let client = tokio::task::spawn_blocking(move || send(port, &sent));
+        let (stream, _) = listener.accept().await.unwrap();
+        assert_eq!(read_request(stream, "secret").await, Some(request));
+        assert!(client.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_launch_request_rejects_bad_token() {
+        let listener = TcpListener::from_std(bind().unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let request = LaunchRequest {
+            token: "guess".to_string(),
+            args: vec![],
+            cwd: None,
+        };
+
+        let client = tokio::task::spawn_blocking(move || send(port, &request));
+        let (stream, _) = listener.accept().await.unwrap();
+        assert_eq!(read_request(stream, "secret").await, None);
+        assert!(client.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,411 @@
+//! Application log for Pinokio.
+//! Levelled JSON-lines records with a target and timestamp, written to the
+//! platform log dir and rotated by size.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::ownership;
+
+/// Size at which the current log file is rotated.
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Rotated files kept besides the current one.
+const KEEP_FILES: usize = 5;
+/// Records returned by `get_logs` when no limit is given.
+pub const DEFAULT_READ_LIMIT: usize = 500;
+/// Bytes read at a time when reading a log file backwards.
+const READ_CHUNK: u64 = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+}
+
+/// One line of the log.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Milliseconds since the Unix epoch.
+    pub ts: u64,
+    pub level: Level,
+    /// Part of the app the record comes from, e.g. `supervisor`.
+    pub target: String,
+    pub message: String,
+}
+
+/// Which records `get_logs` returns.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LogFilter {
+    /// Lowest level to include.
+    pub level: Option<Level>,
+    pub target: Option<String>,
+    /// Text the message must contain, ignoring case.
+    pub contains: Option<String>,
+}
+
+impl LogFilter {
+    fn matches(&self, record: &Record) -> bool {
+        self.level.is_none_or(|level| record.level >= level)
+            && self.target.as_ref().is_none_or(|target| &record.target == target)
+            && self.contains.as_ref().is_none_or(|text| {
+                record.message.to_lowercase().contains(&text.to_lowercase())
+            })
+    }
+}
+
+/// Where the log lives: `~/Library/Logs/Pinokio` on macOS, the local state
+/// or app-data dir elsewhere.
+pub fn log_dir() -> PathBuf {
+    #[cfg(target_os = "macos")]
+    let dir = dirs::home_dir().map(|home| home.join("Library").join("Logs").join("Pinokio"));
+    #[cfg(not(target_os = "macos"))]
+    let dir = dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|base| base.join("pinokio").join("logs"));
+    dir.unwrap_or_else(|| ownership::data_dir().join("logs"))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Open a log file only the owner can read.
+pub fn open_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// The lines in the first `len` bytes of a file, last line first.
+struct RevLines {
+    file: File,
+    pos: u64,
+    /// Bytes read but not yet returned, always the start of a line.
+    buf: Vec<u8>,
+}
+
+impl RevLines {
+    fn new(file: File, len: u64) -> Self {
+        Self {
+            file,
+            pos: len,
+            buf: Vec::new(),
+        }
+    }
+
+    fn read_chunk(&mut self) -> std::io::Result<()> {
+        let step = self.pos.min(READ_CHUNK);
+        self.pos -= step;
+        let mut chunk = vec![0u8; step as usize];
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        self.file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&self.buf);
+        self.buf = chunk;
+        Ok(())
+    }
+}
+
+impl Iterator for RevLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(newline) = self.buf.iter().rposition(|b| *b == b'\n') {
+                let line = self.buf.split_off(newline + 1);
+                self.buf.truncate(newline);
+                if line.is_empty() {
+                    continue;
+                }
+                return Some(String::from_utf8_lossy(&line).into_owned());
+            }
+            if self.pos == 0 {
+                let line = std::mem::take(&mut self.buf);
+                return (!line.is_empty()).then(|| String::from_utf8_lossy(&line).into_owned());
+            }
+            self.read_chunk().ok()?;
+        }
+    }
+}
+
+struct Current {
+    file: Option<File>,
+    size: u64,
+}
+
+pub struct Logger {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    min_level: Level,
+    current: Mutex<Current>,
+}
+
+impl Logger {
+    pub fn new(dir: PathBuf, max_bytes: u64, keep: usize, min_level: Level) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            keep,
+            min_level,
+            current: Mutex::new(Current { file: None, size: 0 }),
+        }
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join("pinokio.log"),
+            n => self.dir.join(format!("pinokio.{}.log", n)),
+        }
+    }
+
+    fn open(&self, current: &mut Current) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700));
+        }
+        let file = open_private(&self.path(0))?;
+        current.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        current.file = Some(file);
+        Ok(())
+    }
+
+    /// Shift `pinokio.log` to `pinokio.1.log` and so on, dropping the oldest.
+    fn rotate(&self, current: &mut Current) {
+        current.file = None;
+        let _ = fs::remove_file(self.path(self.keep));
+        for index in (0..self.keep).rev() {
+            let _ = fs::rename(self.path(index), self.path(index + 1));
+        }
+    }
+
+    pub fn log(&self, level: Level, target: &str, message: String) {
+        if level < self.min_level {
+            return;
+        }
+        let record = Record {
+            ts: now_ms(),
+            level,
+            target: target.to_string(),
+            message,
+        };
+        if cfg!(debug_assertions) {
+            eprintln!("[{:?}] [{}] {}", record.level, record.target, record.message);
+        }
+        let mut line = serde_json::to_string(&record).unwrap_or_default();
+        line.push('\n');
+
+        let mut current = self.current.lock().unwrap();
+        if current.file.is_some() && current.size + line.len() as u64 > self.max_bytes {
+            self.rotate(&mut current);
+        }
+        if current.file.is_none() && self.open(&mut current).is_err() {
+            return;
+        }
+        if let Some(file) = current.file.as_mut() {
+            if file.write_all(line.as_bytes()).is_ok() {
+                current.size += line.len() as u64;
+            }
+        }
+    }
+
+    /// The newest `limit` records matching `filter` from `since` on, oldest
+    /// first. Reads backwards from the newest record and stops as soon as it
+    /// has enough.
+    pub fn read(&self, filter: &LogFilter, since: Option<u64>, limit: usize) -> Vec<Record> {
+        // Open the files under the lock so a rotation can't move them in
+        // between, but read them without holding up logging.
+        let files: Vec<(File, u64)> = {
+            let _current = self.current.lock().unwrap();
+            (0..=self.keep)
+                .filter_map(|index| {
+                    let file = File::open(self.path(index)).ok()?;
+                    let len = file.metadata().ok()?.len();
+                    Some((file, len))
+                })
+                .collect()
+        };
+        let mut records = Vec::new();
+        'files: for (file, len) in files {
+            for line in RevLines::new(file, len) {
+                if records.len() >= limit {
+                    break 'files;
+                }
+                let Ok(record) = serde_json::from_str::<Record>(&line) else {
+                    continue;
+                };
+                if since.is_some_and(|since| record.ts < since) {
+                    break 'files;
+                }
+                if filter.matches(&record) {
+                    records.push(record);
+                }
+            }
+        }
+        records.reverse();
+        records
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// The app's logger. Set `PINOKIO_LOG` to `debug`, `warn`, ... to change
+/// what gets recorded; the default is `info`.
+pub fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| {
+        let level = std::env::var("PINOKIO_LOG")
+            .ok()
+            .and_then(|name| Level::parse(&name))
+            .unwrap_or(Level::Info);
+        Logger::new(log_dir(), MAX_FILE_BYTES, KEEP_FILES, level)
+    })
+}
+
+pub fn log(level: Level, target: &str, message: String) {
+    logger().log(level, target, message);
+}
+
+macro_rules! debug {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Debug, $target, format!($($arg)+))
+    };
+}
+
+macro_rules! info {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Info, $target, format!($($arg)+))
+    };
+}
+
+macro_rules! warn {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Warn, $target, format!($($arg)+))
+    };
+}
+
+macro_rules! error {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::Level::Error, $target, format!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+
+    #[test]
+    fn test_rotation_keeps_newest_files() {
+        let dir = temp_dir("logging-rotate");
+        let logger = Logger::new(dir.clone(), 300, 2, Level::Info);
+        for i in 0..40 {
+            logger.log(Level::Info, "test", format!("message {}", i));
+        }
+        assert!(dir.join("pinokio.log").exists());
+        assert!(dir.join("pinokio.2.log").exists());
+        assert!(!dir.join("pinokio.3.log").exists());
+        assert!(fs::metadata(dir.join("pinokio.log")).unwrap().len() <= 300);
+
+        let records = logger.read(&LogFilter::default(), None, 1000);
+        assert_eq!(records.last().unwrap().message, "message 39");
+        assert!(records.windows(2).all(|pair| pair[0].ts <= pair[1].ts));
+
+        // The newest records span the current and rotated files.
+        let messages: Vec<String> = logger
+            .read(&LogFilter::default(), None, 5)
+            .into_iter()
+            .map(|r| r.message)
+            .collect();
+        assert_eq!(messages, (35..40).map(|i| format!("message {}", i)).collect::<Vec<_>>());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_read_filters() {
+        let dir = temp_dir("logging-filter");
+        let logger = Logger::new(dir.clone(), MAX_FILE_BYTES, KEEP_FILES, Level::Debug);
+        logger.log(Level::Trace, "supervisor", "dropped".to_string());
+        logger.log(Level::Debug, "supervisor", "Spawning backend".to_string());
+        logger.log(Level::Error, "supervisor", "Backend crashed".to_string());
+        logger.log(Level::Warn, "updater", "Update check failed".to_string());
+
+        let all = logger.read(&LogFilter::default(), None, 10);
+        assert_eq!(all.len(), 3);
+
+        let warnings = LogFilter {
+            level: Some(Level::Warn),
+            ..Default::default()
+        };
+        let messages: Vec<String> = logger.read(&warnings, None, 10).into_iter().map(|r| r.message).collect();
+        assert_eq!(messages, vec!["Backend crashed", "Update check failed"]);
+
+        let supervisor = LogFilter {
+            target: Some("supervisor".to_string()),
+            contains: Some("CRASHED".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(logger.read(&supervisor, None, 10).len(), 1);
+        assert_eq!(logger.read(&LogFilter::default(), None, 1)[0].message, "Update check failed");
+        assert!(logger.read(&LogFilter::default(), Some(u64::MAX), 10).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rev_lines() {
+        let dir = temp_dir("logging-rev");
+        let path = dir.join("lines");
+        let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, format!("{}partial", text)).unwrap();
+
+        let len = text.len() as u64;
+        let lines: Vec<String> = RevLines::new(File::open(&path).unwrap(), len).collect();
+        assert_eq!(lines.len(), 20_000);
+        assert_eq!(lines[0], "line 19999");
+        assert_eq!(lines[19_999], "line 0");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_log_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("logging-private");
+        let logger = Logger::new(dir.clone(), MAX_FILE_BYTES, KEEP_FILES, Level::Info);
+        logger.log(Level::Info, "test", "secret".to_string());
+        let mode = fs::metadata(dir.join("pinokio.log")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -6,71 +6,70 @@
     windows_subsystem = "windows"
 )]
 
+#[macro_use]
+mod logging;
+mod backend;
 mod commands;
+mod instance;
+mod shutdown;
+mod signal;
 #[cfg(test)]
 mod tests;
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-
-use std::process::Stdio;
 use tauri::{CustomMenuItem, SystemTray, SystemTrayMenu, SystemTrayEvent, Manager, GlobalShortcutManager};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use commands::process_manager::ProcessManagerState;
-
-fn log_to_file(msg: &str) {
-    use std::fs::OpenOptions;
-    use std::io::Write;
-    let _ = std::fs::create_dir_all("/tmp");
-    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open("/tmp/pinokio_debug.log") {
-         let _ = writeln!(file, "{}", msg);
-    }
-}
+use backend::supervisor::SupervisorConfig;
+use backend::ownership::{self, AcquireError, InstanceLock};
+use backend::{port, BackendState};
+use commands::{ProcessManagerState, PtyState};
 
-fn kill_zombies() {
-    log_to_file("[CLEANUP] Checking for zombie processes on port 42000...");
-    
-    // 1. Force kill anything on port 42000
-    // We use "sh -c" to leverage shell pipes for lsof | xargs
-    let output_port = std::process::Command::new("sh")
-        .arg("-c")
-        .arg("lsof -t -i:42000 | xargs -r kill -9")
-        .output();
+fn main() {
+    info!("main", "Pinokio {} starting...", env!("CARGO_PKG_VERSION"));
 
-    match output_port {
-        Ok(o) => {
-            log_to_file(&format!("[CLEANUP] Port 42000 sweep complete. Success: {}", o.status.success()));
-        },
+    // Take the launcher lock before doing anything else. This also reaps a
+    // backend orphaned by a previous launcher that died.
+    let lock_path = ownership::lock_path();
+    let instance_lock = match InstanceLock::acquire(lock_path.clone()) {
+        Ok(lock) => lock,
+        Err(AcquireError::AlreadyRunning(_)) => {
+            // Hand our arguments to the running instance and get out of the way.
+            match instance::forward(&lock_path) {
+                Ok(()) => {
+                    info!("instance", "Forwarded launch to the running instance.");
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    error!("instance", "Pinokio is already running: {}", e);
+                    eprintln!("Pinokio is already running: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
         Err(e) => {
-            log_to_file(&format!("[CLEANUP] Warning: Failed to sweep port 42000: {}", e));
+            error!("ownership", "Refusing to start: {}", e);
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
-    }
-
-    // 2. Force kill orphaned pinokiod scripts
-    log_to_file("[CLEANUP] Sweeping for orphaned pinokiod scripts...");
-    let output_pkill = std::process::Command::new("pkill")
-        .arg("-f")
-        .arg("pinokiod/script/index.js")
-        .output();
-        
-    match output_pkill {
-         Ok(o) => {
-             // pkill returns exit code 1 if nothing matched (which is good), so we don't assume only Success=true is good.
-             log_to_file(&format!("[CLEANUP] Orphan sweep complete. (Exit code: {:?})", o.status.code()));
-         },
-         Err(e) => {
-             log_to_file(&format!("[CLEANUP] Warning: Failed to sweep orphans: {}", e));
-         }
-    }
-}
+    };
 
-fn main() {
-    log_to_file("----------------------------------------");
-    log_to_file("Pinokio Starting...");
+    let instance_socket = match instance::bind().and_then(|listener| Ok((listener, instance::new_token()?))) {
+        Ok((listener, token)) => {
+            if let Ok(addr) = listener.local_addr() {
+                instance_lock.set_ipc(addr.port(), token.clone());
+            }
+            Some((listener, token))
+        }
+        Err(e) => {
+            warn!("instance", "{}", e);
+            None
+        }
+    };
 
-    // Auto-Cleanup: Kill zombies before doing anything else
-    kill_zombies();
+    let preferred_port = port::configured_port().unwrap_or(port::DEFAULT_PORT);
+    let backend_port = port::select_port(preferred_port).unwrap_or_else(|e| {
+        warn!("port", "{}, falling back to {}", e, preferred_port);
+        preferred_port
+    });
+    info!("port", "Backend will listen on port {}", backend_port);
 
     // System tray menu
     let quit = CustomMenuItem::new("quit".to_string(), "Quit");
@@ -81,21 +80,22 @@ fn main() {
         .add_item(quit);
     let system_tray = SystemTray::new().with_menu(tray_menu);
 
-    let process_state = ProcessManagerState {
-        processes: Arc::new(Mutex::new(HashMap::new())),
-    };
+    let process_state = ProcessManagerState::new();
 
     tauri::Builder::default()
         .manage(process_state)
+        .manage(PtyState::default())
+        .manage(BackendState::new(backend_port))
+        .manage(instance_lock)
         .system_tray(system_tray)
-        .on_window_event(|event| match event.event() {
-            tauri::WindowEvent::CloseRequested { .. } => {
+        .on_window_event(|event| {
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event.event() {
                 let window = event.window();
                 if window.label() == "main" {
-                    std::process::exit(0);
+                    api.prevent_close();
+                    shutdown::quit(&window.app_handle());
                 }
             }
-            _ => {}
         })
         .plugin(tauri_plugin_store::Builder::default().build())
         .setup(|app| {
@@ -114,35 +114,51 @@ fn main() {
                 }
             });
 
+            // Keep CPU/memory of managed processes current and adopt the backend's children
+            commands::monitor::spawn(
+                app.handle(),
+                app.state::<ProcessManagerState>().inner().clone(),
+                app.state::<BackendState>().inner().clone(),
+                commands::monitor::SAMPLE_INTERVAL,
+            );
+
+            // Let later launches hand over to this instance
+            if let Some((listener, token)) = instance_socket {
+                tauri::async_runtime::spawn(instance::serve(app.handle(), listener, token));
+            }
+
             // Check for updates
             let updater_handle = app.handle();
             tauri::async_runtime::spawn(async move {
-                log_to_file("[UPDATER] Checking for updates...");
+                info!("updater", "Checking for updates...");
                 match updater_handle.updater().check().await {
                     Ok(update) => {
                         if update.is_update_available() {
-                            log_to_file("[UPDATER] Update available! Downloading and installing...");
+                            info!("updater", "Update available! Downloading and installing...");
                             if let Err(e) = update.download_and_install().await {
-                                log_to_file(&format!("[UPDATER] Failed to update: {}", e));
+                                error!("updater", "Failed to update: {}", e);
                             } else {
-                                log_to_file("[UPDATER] Update installed. Restarting application...");
+                                info!("updater", "Update installed. Restarting application...");
+                                let stop_handle = updater_handle.clone();
+                                let _ = tauri::async_runtime::spawn_blocking(move || {
+                                    shutdown::stop_all(&stop_handle, &shutdown::ShutdownConfig::from_env());
+                                })
+                                .await;
                                 updater_handle.restart();
                             }
                         } else {
-                            log_to_file("[UPDATER] No updates available.");
+                            info!("updater", "No updates available.");
                         }
                     }
                     Err(e) => {
-                        log_to_file(&format!("[UPDATER] Failed to check for updates: {}", e));
+                        warn!("updater", "Failed to check for updates: {}", e);
                     }
                 }
             });
 
-            // Spawn Pinokio Backend
+            // Spawn and supervise the Pinokio backend
             let app_handle = app.handle();
             tauri::async_runtime::spawn(async move {
-                log_to_file("Attempting to spawn backend...");
-                
                 // Diagnostic: Try to resolve with "node_modules" prefix and log paths
                 // We changed the resource to "node_modules_vendor" in tauri.conf.json
                 let resource_path = "node_modules_vendor/pinokiod/script/index.js";
@@ -152,7 +168,7 @@ fn main() {
                     .resolve_resource(resource_path)
                     .unwrap_or_else(|| {
                          let msg = "[PINOKIO DIAG] Failed to resolve primary resource path, trying fallback...";
-                         log_to_file(msg);
+                         warn!("diag", "{}", msg);
                          println!("{}", msg);
                          // If the vendor link failed, maybe it's flattened? 
                          // But we expect it to be "node_modules_vendor"
@@ -162,21 +178,21 @@ fn main() {
                 
                 let script_path = script_path_buf.to_string_lossy().to_string();
                 let msg = format!("[PINOKIO DIAG] Resolved script path: {}", script_path);
-                log_to_file(&msg);
+                debug!("diag", "{}", msg);
                 println!("{}", msg);
 
                 // DIAGNOSTIC: List files around the target
-                log_to_file("[PINOKIO DIAG] Listing parent directory of script:");
+                debug!("diag", "Listing parent directory of script:");
                 if let Some(parent) = std::path::Path::new(&script_path).parent() {
                      let output = std::process::Command::new("ls")
                         .arg("-la")
                         .arg(parent)
                         .output();
                      if let Ok(o) = output {
-                         log_to_file(&format!("ls parent: {:?}", String::from_utf8_lossy(&o.stdout)));
+                         debug!("diag", "ls parent: {:?}", String::from_utf8_lossy(&o.stdout));
                      }
                         
-                     log_to_file("[PINOKIO DIAG] Listing node_modules directory:");
+                     debug!("diag", "Listing node_modules directory:");
                      if let Some(gradparent) = parent.parent() { // pinokiod
                          if let Some(greatgrandparent) = gradparent.parent() { // node_modules
                              let output = std::process::Command::new("ls")
@@ -184,7 +200,7 @@ fn main() {
                                 .arg(greatgrandparent)
                                 .output();
                              if let Ok(o) = output {
-                                 log_to_file(&format!("ls node_modules: {:?}", String::from_utf8_lossy(&o.stdout)));
+                                 debug!("diag", "ls node_modules: {:?}", String::from_utf8_lossy(&o.stdout));
                              }
                                 
                              // Also list root of mount if possible
@@ -194,7 +210,7 @@ fn main() {
                                     .arg(root)
                                     .output();
                                  if let Ok(o) = output {
-                                     log_to_file(&format!("ls root: {:?}", String::from_utf8_lossy(&o.stdout)));
+                                     debug!("diag", "ls root: {:?}", String::from_utf8_lossy(&o.stdout));
                                  }
                              }
                          }
@@ -202,80 +218,12 @@ fn main() {
                 }
 
                 
-                let mut cmd = Command::new("node");
-                cmd.arg(&script_path);
-
-                // Performance Optimization (v5.3.11)
-                // 1. Increase threadpool for blocking I/O (Filesystem, Crypto, Zlib)
-                cmd.env("UV_THREADPOOL_SIZE", "128"); 
-                // 2. Allow more RAM for Node.js to reduce GC pauses (4GB)
-                cmd.env("NODE_OPTIONS", "--max-old-space-size=4096");
-                
-                // Fix for bundled environment: Set NODE_PATH to node_modules_vendor
-                if let Some(script_dir) = script_path_buf.parent() {
-                    if let Some(pkg_dir) = script_dir.parent() {
-                        if let Some(vendor_dir) = pkg_dir.parent() {
-                             let vendor_path = vendor_dir.to_string_lossy().to_string();
-                             log_to_file(&format!("[PINOKIO FIX] Setting NODE_PATH to: {}", vendor_path));
-                             cmd.env("NODE_PATH", vendor_path);
-                        }
-                    }
-                }
-
-                cmd.stdout(Stdio::piped());
-                cmd.stderr(Stdio::piped());
-
-                match cmd.spawn() {
-                    Ok(mut child) => {
-                        log_to_file("Node process spawned successfully.");
-                        let stdout = child.stdout.take().expect("Failed to open stdout");
-                        let stderr = child.stderr.take().expect("Failed to open stderr");
-                        
-                        let window_handle = app_handle.clone();
-                        let window_handle_err = app_handle.clone();
-
-                        // Stream stdout
-                        tokio::spawn(async move {
-                            let reader = BufReader::new(stdout);
-                            let mut lines = reader.lines();
-                            while let Ok(Some(line)) = lines.next_line().await {
-                                log_to_file(&format!("[NODE STDOUT] {}", line));
-                                println!("[PINOKIO] {}", line); // Log to terminal
-                                if let Some(window) = window_handle.get_window("main") {
-                                    let _ = window.emit("terminal:stdout", &line);
-                                    if line.contains("Server listening on port") {
-                                         let _ = window.emit("terminal:stdout", "Server ready, launching...");
-                                         // Small delay to ensure server is fully ready to accept connections
-                                         tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-                                         log_to_file("Server ready, redirecting...");
-                                         let _ = window.eval("window.location.replace('http://localhost:42000')");
-                                    }
-                                }
-                            }
-                        });
-
-                        // Stream stderr
-                        tokio::spawn(async move {
-                            let reader = BufReader::new(stderr);
-                            let mut lines = reader.lines();
-                            while let Ok(Some(line)) = lines.next_line().await {
-                                log_to_file(&format!("[NODE STDERR] {}", line));
-                                eprintln!("[PINOKIO ERR] {}", line);
-                                if let Some(window) = window_handle_err.get_window("main") {
-                                    let _ = window.emit("terminal:stderr", &line);
-                                }
-                            }
-                        });
-                    }
-                    Err(e) => {
-                         let msg = format!("Failed to spawn node process: {}", e);
-                         log_to_file(&msg);
-                         eprintln!("{}", msg);
-                         if let Some(window) = app_handle.get_window("main") {
-                             let _ = window.emit("terminal:stderr", &format!("Failed to spawn backend: {}", e));
-                         }
-                    }
-                }
+                backend::supervisor::supervise(
+                    app_handle,
+                    script_path_buf,
+                    SupervisorConfig::default(),
+                )
+                .await;
             });
             
             Ok(())
@@ -289,7 +237,7 @@ fn main() {
             }
             SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
                 "quit" => {
-                    std::process::exit(0);
+                    shutdown::quit(app);
                 }
                 "show" => {
                     if let Some(window) = app.get_window("main") {
@@ -304,7 +252,19 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             // Terminal commands
             commands::run_command,
+            commands::spawn_command,
+            commands::wait_command,
+            commands::get_command_status,
+            commands::get_run_output,
+            commands::kill_command,
+            commands::write_stdin,
+            commands::close_stdin,
             commands::kill_process,
+            // PTY sessions
+            commands::pty::pty_open,
+            commands::pty::pty_write,
+            commands::pty::pty_resize,
+            commands::pty::pty_close,
             // Filesystem commands
             commands::list_directory,
             commands::read_file,
@@ -315,8 +275,18 @@ fn main() {
             commands::remove_path,
             // Process manager commands
             commands::process_manager::get_processes,
+            commands::process_manager::get_process_history,
             commands::process_manager::detect_conda,
             commands::process_manager::get_system_resources,
+            commands::ports::check_ports,
+            commands::ports::find_free_port,
+            // Backend commands
+            commands::backend::get_backend_status,
+            commands::backend::get_backend_port,
+            commands::backend::restart_backend,
+            // Application log
+            commands::logs::get_logs,
+            commands::diagnostics::export_diagnostics,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,151 @@
+//! Graceful shutdown for Pinokio.
+//! Stops the backend and every managed process before the launcher exits.
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+
+use crate::backend::ownership::{self, InstanceLock};
+use crate::backend::BackendState;
+use crate::commands::{monitor, ProcessManagerState, PtyState};
+use crate::signal::{self, Signal};
+
+pub struct ShutdownConfig {
+    /// How long processes get to exit after SIGTERM before being killed.
+    pub grace: Duration,
+    /// How long stopped runs get to flush their logs and record their exits.
+    pub settle: Duration,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace: Duration::from_secs(5),
+            settle: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ShutdownConfig {
+    /// Defaults, with the grace period overridable via `PINOKIO_SHUTDOWN_GRACE_MS`.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(ms) = std::env::var("PINOKIO_SHUTDOWN_GRACE_MS")
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+        {
+            config.grace = Duration::from_millis(ms);
+        }
+        config
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    ownership::stamp(pid).is_some()
+}
+
+/// SIGTERM every process group, wait up to `grace`, then SIGKILL what is
+/// left. Returns the pids that had to be killed.
+pub fn terminate_all(pids: &[u32], grace: Duration) -> Vec<u32> {
+    for pid in pids {
+        let _ = monitor::signal_tree(*pid, Signal::Term);
+    }
+
+    let deadline = Instant::now() + grace;
+    let mut remaining: Vec<u32> = pids.to_vec();
+    while !remaining.is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+        remaining.retain(|pid| is_running(*pid));
+    }
+
+    for pid in &remaining {
+        warn!("shutdown", "pid {} ignored SIGTERM, killing", pid);
+        let _ = monitor::signal_tree(*pid, Signal::Kill);
+    }
+    // Leaders that exited may still have children in their group.
+    for pid in pids.iter().filter(|pid| !remaining.contains(pid)) {
+        let _ = signal::send_group(*pid, Signal::Kill);
+    }
+    remaining
+}
+
+/// Stop the backend and all managed processes, then release the launcher
+/// lock. Safe to call more than once; only the first call does the work.
+pub fn stop_all(app: &AppHandle, config: &ShutdownConfig) {
+    let backend = app.state::<BackendState>();
+    if backend.stopping.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("shutdown", "Stopping backend and managed processes...");
+
+    // Keep restart policies from bringing runs back once they are signalled.
+    let processes = app.state::<ProcessManagerState>();
+    processes.stop_all_runs();
+    let mut pids: Vec<u32> = processes.processes.lock().unwrap().keys().copied().collect();
+    pids.extend(
+        app.state::<PtyState>()
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|session| session.pid),
+    );
+    pids.extend(backend.snapshot().pid);
+
+    let killed = terminate_all(&pids, config.grace);
+    info!(
+        "shutdown",
+        "Stopped {} process(es), {} needed SIGKILL",
+        pids.len(),
+        killed.len()
+    );
+    if !processes.wait_settled(config.settle) {
+        warn!("shutdown", "Some runs did not finish within {:?}; their logs may be incomplete", config.settle);
+    }
+
+    if let Some(lock) = app.try_state::<InstanceLock>() {
+        lock.release();
+    }
+}
+
+/// Run the shutdown routine off the UI thread and exit the launcher.
+pub fn quit(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.hide();
+    }
+    let app = app.clone();
+    std::thread::spawn(move || {
+        stop_all(&app, &ShutdownConfig::from_env());
+        info!("shutdown", "Bye.");
+        use std::io::Write;
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        std::process::exit(0);
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminate_all_stops_children() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let killed = terminate_all(&[child.id()], Duration::from_secs(2));
+        assert!(killed.is_empty());
+        assert!(!child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_terminate_all_escalates_to_sigkill() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; exec sleep 30"])
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let killed = terminate_all(&[child.id()], Duration::from_millis(300));
+        assert_eq!(killed, vec![child.id()]);
+        assert!(!child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,207 @@
+//! Process signalling for Pinokio.
+//! Processes we spawn lead their own process group, so a whole tree can be
+//! signalled at once.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Signal {
+    Term,
+    Int,
+    Kill,
+    Hup,
+}
+
+impl Signal {
+    /// Parse `TERM`, `SIGTERM`, `term`, ... into a signal.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let upper = name.trim().to_ascii_uppercase();
+        match upper.strip_prefix("SIG").unwrap_or(&upper) {
+            "TERM" => Ok(Signal::Term),
+            "INT" => Ok(Signal::Int),
+            "KILL" => Ok(Signal::Kill),
+            "HUP" => Ok(Signal::Hup),
+            _ => Err(format!("Unsupported signal: {}", name)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Signal::Term => libc::SIGTERM,
+            Signal::Int => libc::SIGINT,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Hup => libc::SIGHUP,
+        }
+    }
+}
+
+/// Put a command in a new process group led by the child itself.
+pub fn isolate(command: &mut tokio::process::Command) {
+    #[cfg(unix)]
+    command.process_group(0);
+    #[cfg(windows)]
+    {
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    }
+}
+
+/// Whether `pid` leads its own process group, as processes started with
+/// `isolate` do.
+pub fn leads_group(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        unsafe { libc::getpgid(pid as libc::pid_t) == pid as libc::pid_t }
+    }
+    #[cfg(windows)]
+    {
+        // taskkill /T walks the tree itself.
+        let _ = pid;
+        true
+    }
+}
+
+/// Send `signal` to `pid`, or to its whole process group when `tree` is set.
+/// A `tree` signal fails if `pid` does not lead a group. Only use it while
+/// `pid` is known to be alive; see `send_group` otherwise.
+pub fn send(pid: u32, signal: Signal, tree: bool) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        let raw = signal.as_raw();
+        if tree && !leads_group(pid) {
+            return Err(format!("Failed to send SIG{:?} to {}: not a process group leader", signal, pid));
+        }
+        let sent = if tree {
+            unsafe { libc::killpg(pid as libc::pid_t, raw) }
+        } else {
+            unsafe { libc::kill(pid as libc::pid_t, raw) }
+        };
+        if sent == 0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "Failed to send SIG{:?} to {}: {}",
+                signal,
+                pid,
+                std::io::Error::last_os_error()
+            ))
+        }
+    }
+    #[cfg(windows)]
+    {
+        // Windows has no signals; anything but KILL asks politely.
+        let mut cmd = std::process::Command::new("taskkill");
+        cmd.args(["/PID", &pid.to_string()]);
+        if tree {
+            cmd.arg("/T");
+        }
+        if signal == Signal::Kill {
+            cmd.arg("/F");
+        }
+        let status = cmd.status().map_err(|e| format!("Kill failed: {}", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("taskkill failed for {} ({:?})", pid, status.code()))
+        }
+    }
+}
+
+/// Send `signal` to what is left of the process group `pgid` after its
+/// leader was reaped. Never signals a single process, since the leader's
+/// PID may have been reused; an empty group counts as done.
+pub fn send_group(pgid: u32, signal: Signal) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        if unsafe { libc::killpg(pgid as libc::pid_t, signal.as_raw()) } == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ESRCH) {
+            Ok(())
+        } else {
+            Err(format!("Failed to send SIG{:?} to group {}: {}", signal, pgid, err))
+        }
+    }
+    #[cfg(windows)]
+    {
+        // Without its leader the tree can't be found again on Windows.
+        let _ = (pgid, signal);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(Signal::parse("TERM"), Ok(Signal::Term));
+        assert_eq!(Signal::parse("sigint"), Ok(Signal::Int));
+        assert_eq!(Signal::parse(" Kill "), Ok(Signal::Kill));
+        assert_eq!(Signal::parse("SIGHUP"), Ok(Signal::Hup));
+        assert!(Signal::parse("USR1").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_send_tree_kills_grandchildren() {
+        let mut command = tokio::process::Command::new("sh");
+        command.args(["-c", "sleep 30 & echo $!; wait"]);
+        command.stdout(std::process::Stdio::piped());
+        isolate(&mut command);
+        let mut child = command.spawn().unwrap();
+
+        use tokio::io::AsyncBufReadExt;
+        let stdout = child.stdout.take().unwrap();
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        let grandchild: u32 = lines.next_line().await.unwrap().unwrap().trim().parse().unwrap();
+
+        send(child.id().unwrap(), Signal::Kill, true).unwrap();
+        child.wait().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(crate::backend::ownership::stamp(grandchild).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_reports_failure() {
+        // Reap a child so its pid is known to be gone.
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(send(pid, Signal::Term, false).is_err());
+        assert!(send_group(pid, Signal::Kill).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tree_signal_needs_group_leader() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        assert!(!leads_group(child.id()));
+        assert!(send(child.id(), Signal::Kill, true).is_err());
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_send_group_after_leader_exits() {
+        let mut command = tokio::process::Command::new("sh");
+        command.args(["-c", "sleep 30 >/dev/null & echo $!"]);
+        command.stdout(std::process::Stdio::piped());
+        isolate(&mut command);
+        let child = command.spawn().unwrap();
+        let leader = child.id().unwrap();
+        let output = child.wait_with_output().await.unwrap();
+        let grandchild: u32 = String::from_utf8_lossy(&output.stdout).trim().parse().unwrap();
+
+        // The leader is reaped; its group lives on in the grandchild.
+        send_group(leader, Signal::Kill).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(crate::backend::ownership::stamp(grandchild).is_none());
+    }
+}
@@ -1,3 +1,16 @@
+//! Helpers shared by the unit tests.
+
+use std::path::PathBuf;
+
+/// A fresh, empty directory under the system temp dir, unique to this test
+/// run and `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pinokio-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 #[cfg(test)]
 mod tests {
     #[test]